@@ -22,7 +22,7 @@ fn main() {
 }
 
 /*- GET endpoint inside of the controlled stack -*/
-fn test(stream: &mut Stream) -> () {
+fn test(stream: &mut Stream) {
     stream.respond(
         200u16,
         Respond::new().text(r#"You have the "Host" header!"#),
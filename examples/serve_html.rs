@@ -26,7 +26,7 @@ fn main() {
 }
 
 /*- Api endpoints -*/
-fn manual_serve(stream: &mut Stream) -> () {
+fn manual_serve(stream: &mut Stream) {
     /*- Respond with the html file -*/
     stream.respond_file(200u16, "examples/static/manual_serve.html");
 }
@@ -23,7 +23,7 @@ fn main() {
         .unwrap();
 }
 
-fn api_endpoint_with_url_params(stream: &mut Stream) -> () {
+fn api_endpoint_with_url_params(stream: &mut Stream) {
     stream.respond(
         200u16,
         Respond::new().text(&format!(
@@ -0,0 +1,143 @@
+/*- Imports -*/
+use crate::stream::Stream;
+use serde_json::{json, Map, Value};
+
+/*- Constants -*/
+const REDACTED: &str = "[REDACTED]";
+
+/*- Structs, enums & unions -*/
+/// What the audit log, (see `Server::audit_log`) leaves out of its
+/// entries. Header names and json fields are compared case-insensitively.
+///
+/// ## Examples
+/// ```
+/// use responder::prelude::*;
+/// use responder::audit::AuditConfig;
+///
+/// Server::new().audit_log(AuditConfig {
+///     redact_json_fields: &["password", "token", "ssn"],
+///     ..AuditConfig::DEFAULT
+/// });
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct AuditConfig {
+    /// Request headers whose values are replaced with `[REDACTED]`
+    pub redact_headers: &'static [&'static str],
+
+    /// Fields of json bodies whose values are replaced with `[REDACTED]`,
+    /// no matter how deeply nested they are
+    pub redact_json_fields: &'static [&'static str],
+
+    /// Bodies longer than this many bytes are truncated
+    pub max_body: usize,
+
+    /// Where entries are written, (`audit::stderr` by default)
+    pub sink: fn(&Value),
+}
+
+/*- Method implementations -*/
+impl AuditConfig {
+    /// Redacts credential headers and common secret fields, keeps
+    /// the first 4 KiB of bodies and writes entries to stderr
+    pub const DEFAULT: AuditConfig = AuditConfig {
+        redact_headers: &["Authorization", "Proxy-Authorization", "Cookie"],
+        redact_json_fields: &["password", "token", "secret"],
+        max_body: 4096,
+        sink: stderr,
+    };
+}
+
+/*- Functions -*/
+/// Write an audit log entry to stderr as a line of json, like
+/// the server's other logs. The default sink of `AuditConfig`
+pub fn stderr(entry: &Value) {
+    eprintln!("[responder] {entry}");
+}
+
+/*- Pass the audit log entry of a request to the configured sink -*/
+pub(crate) fn log(stream: &Stream, config: &AuditConfig) {
+    (config.sink)(&entry(stream, config));
+}
+
+/*- Build the audit log entry of a request which has been handled -*/
+fn entry(stream: &Stream, config: &AuditConfig) -> Value {
+    let headers: Map<String, Value> = stream
+        .header_list()
+        .iter()
+        .map(|(key, value)| {
+            let value: &str = match redacted(config.redact_headers, key) {
+                true => REDACTED,
+                false => value,
+            };
+            (key.to_string(), Value::from(value))
+        })
+        .collect();
+
+    json!({
+        "method": stream.method().as_str(),
+        "path": stream.path(),
+        "status": stream.status(),
+        "duration_ms": stream.elapsed().as_millis() as u64,
+        "request_headers": headers,
+        "request_body": body(stream.raw_body(), config),
+        "response_body": body(stream.captured_response(), config),
+    })
+}
+
+/*- Json bodies are redacted before being truncated. Other bodies, (like forms,
+or json which was cut off while capturing the response) can't be redacted field
+by field, so they're left out entirely if they mention any of the fields -*/
+fn body(body: &[u8], config: &AuditConfig) -> Value {
+    let text: String = match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) => {
+            redact_fields(&mut value, config.redact_json_fields);
+            value.to_string()
+        }
+        Err(_) => {
+            let text: String = String::from_utf8_lossy(body).to_string();
+            let lowercase: String = text.to_ascii_lowercase();
+            if config
+                .redact_json_fields
+                .iter()
+                .any(|field| lowercase.contains(&field.to_ascii_lowercase()))
+            {
+                return Value::from(REDACTED);
+            };
+            text
+        }
+    };
+    if text.len() <= config.max_body {
+        return Value::from(text);
+    };
+
+    /*- Never cut a character in half -*/
+    let mut end: usize = config.max_body;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    Value::from(format!("{}...", &text[..end]))
+}
+
+/*- Replace the values of redacted fields, in all nested objects and arrays -*/
+fn redact_fields(value: &mut Value, fields: &[&str]) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                match redacted(fields, key) {
+                    true => *value = Value::from(REDACTED),
+                    false => redact_fields(value, fields),
+                };
+            }
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| redact_fields(value, fields)),
+        _ => (),
+    };
+}
+
+fn redacted(names: &[&str], name: &str) -> bool {
+    names
+        .iter()
+        .any(|redacted| redacted.eq_ignore_ascii_case(name))
+}
@@ -4,4 +4,67 @@ pub enum ConfigError {
     MissingPort,
     MissingHost,
     HostPortBindingFail,
+
+    /// A signal handler has already been installed for this process
+    #[cfg(feature = "signal")]
+    SignalHandlerFail,
+}
+
+/// Errors which can occur when getting a typed url parameter using `Stream::param_as`
+#[derive(Debug)]
+pub enum ParamError {
+    /// The route doesn't have a parameter with that name
+    Missing,
+
+    /// The parameter couldn't be parsed as the requested type
+    Invalid,
+}
+
+/// Errors which can occur when forwarding a request using `Stream::proxy_to`
+#[cfg(feature = "proxy")]
+#[derive(Debug)]
+pub enum ProxyError {
+    /// The url wasn't a valid `http://` url
+    InvalidUrl,
+
+    /// The stream has already been responded to
+    AlreadyResponded,
+
+    /// The request method isn't one which can be forwarded
+    UnknownMethod,
+
+    /// Connecting to, writing to or reading from the upstream server failed
+    Io(std::io::Error),
+}
+
+/// Errors which can occur when parsing the request body as JSON
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum JsonError {
+    /// The body was bigger than the allowed size
+    TooLarge,
+
+    /// The body wasn't valid JSON, or didn't match the expected type
+    Invalid(serde_json::Error),
+}
+
+#[cfg(feature = "serde")]
+impl JsonError {
+    /// The http-status which fits the error best (413 or 400)
+    pub fn status(&self) -> u16 {
+        match self {
+            JsonError::TooLarge => 413u16,
+            JsonError::Invalid(_) => 400u16,
+        }
+    }
+}
+
+/// Errors which can occur when decoding the request body using `Stream::body_text`
+#[derive(Debug)]
+pub enum CharsetError {
+    /// The body wasn't valid UTF-8
+    InvalidUtf8(std::string::FromUtf8Error),
+
+    /// The body was declared as US-ASCII, but contained non-ascii bytes
+    InvalidAscii,
 }
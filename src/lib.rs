@@ -5,6 +5,8 @@
 #![allow(dead_code, unused_imports, unused_variables, unused_mut)]
 
 /*- Module imports -*/
+#[cfg(feature = "serde")]
+pub mod audit;
 pub mod errors;
 pub mod prelude;
 pub mod request;
@@ -14,28 +16,106 @@ mod thread_handler;
 mod utils;
 
 /*- Imports -*/
-use crate::response::ResponseType;
+use crate::response::{Cors, ErrorFormat, MissingKeys, ResponseType};
 use errors::ConfigError;
 use lazy_static::lazy_static;
-use request::info::{Method, RequestInfo};
+#[cfg(feature = "serde")]
+pub use request::json::merge_json;
+use request::{
+    info::{Method, RequestInfo},
+    BodyMode,
+};
 pub use response::{not_found, Respond};
 use std::{
+    borrow::Cow,
     collections::HashMap,
     fs,
     io::{Read, Write},
-    net::{TcpListener, TcpStream},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
     path::{Path, PathBuf},
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
 };
+use stream::Framing;
 pub use stream::Stream;
 
 /*- Constants -*/
 const _DATA_BUF_INIT: usize = 1024usize;
 const DATA_BUF_POST_INIT: usize = u16::MAX as usize;
+/*- Chunk size lines (and trailers) longer than this are rejected -*/
+const CHUNK_LINE_MAX: usize = 4096usize;
+/*- Chunked bodies can't be bigger than this, unless `max_body_size` is set -*/
+const CHUNKED_BODY_MAX: usize = 16 * 1024 * 1024;
+/*- The trailers of a chunked body can't be bigger than this in total -*/
+const TRAILERS_MAX: usize = 16 * 1024;
+/*- Paths with more segments than this aren't routed, unless `max_path_segments` is set -*/
+const PATH_SEGMENTS_MAX: usize = 64usize;
+/*- How long the connection reaper lets connections wait for a request, if keep-alive is disabled -*/
+const REAPER_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/*- Compressed bodies can't decode to more than this, unless `max_body_size` is set -*/
+#[cfg(feature = "gzip")]
+const DECODED_BODY_MAX: usize = 16 * 1024 * 1024;
 
 /*- Loading files will check if they're already cached -*/
 lazy_static! {
     pub static ref FILE_CACHE: Mutex<HashMap<String, Vec<u8>>> = Mutex::new(HashMap::new());
+
+    /*- Static file paths which recently didn't exist, and when they were looked up -*/
+    static ref MISS_CACHE: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+}
+
+/*- Cached files which are stored gzip compressed, (see `Server::cache_compressed`) -*/
+#[cfg(feature = "gzip")]
+lazy_static! {
+    static ref GZIP_CACHE: Mutex<HashMap<String, Vec<u8>>> = Mutex::new(HashMap::new());
+}
+
+/*- Lock the file cache. If a thread panicked whilst holding the lock, the
+cache would be unusable forever, so we log it and recover the lock instead -*/
+pub(crate) fn file_cache() -> MutexGuard<'static, HashMap<String, Vec<u8>>> {
+    lock_cache(&FILE_CACHE)
+}
+#[cfg(feature = "gzip")]
+pub(crate) fn gzip_cache() -> MutexGuard<'static, HashMap<String, Vec<u8>>> {
+    lock_cache(&GZIP_CACHE)
+}
+fn lock_cache(
+    cache: &'static Mutex<HashMap<String, Vec<u8>>>,
+) -> MutexGuard<'static, HashMap<String, Vec<u8>>> {
+    cache.lock().unwrap_or_else(|poisoned| {
+        eprintln!("[responder] File cache lock was poisoned by a panicking thread, recovering");
+        cache.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/*- Files are cached using their canonicalized path -*/
+pub(crate) fn cache_key(path: &Path) -> String {
+    path.canonicalize()
+        .unwrap_or(PathBuf::from(""))
+        .display()
+        .to_string()
+}
+
+/*- Copy a file out of the cache, so that the lock isn't held whilst responding -*/
+pub(crate) fn cached_file(path: &Path) -> Option<Vec<u8>> {
+    file_cache().get(&cache_key(path)).cloned()
+}
+#[cfg(feature = "gzip")]
+pub(crate) fn cached_gzip_file(path: &Path) -> Option<Vec<u8>> {
+    gzip_cache().get(&cache_key(path)).cloned()
+}
+
+/*- What to respond to /favicon.ico with -*/
+#[derive(Clone, Copy)]
+enum Favicon {
+    File(&'static str),
+    Bytes(&'static [u8]),
+    None,
 }
 
 /*- What files we want to cache -*/
@@ -45,7 +125,7 @@ enum FileCacheType {
     Selection(&'static [&'static str]),
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 /*- Structs, enums & unions -*/
 /// The Server struct contains changeable fields
 /// which configures the server during both startup and
@@ -60,26 +140,136 @@ pub struct Server {
     /// The maximum number of threads the current server will use.
     num_threads: u16,
 
+    /// Number of threads accepting connections
+    acceptor_threads: u16,
+
+    /// Worker threads are named this, followed by their index
+    thread_name_prefix: String,
+
     /// Serve static files from a directory (nested directories too)
     serve: Option<&'static str>,
 
+    /// Files which are compiled into the binary, by their path
+    embedded: HashMap<String, &'static [u8]>,
+
     /// Path to a 404 page, if not specified server will return "404 Not Found" if endpoint wasn't found
     not_found: Option<&'static str>,
 
     /// All http-routes coupled to this server
-    routes: &'static [Route],
+    routes: Cow<'static, [Route]>,
 
     /// The write buffer size when recieving requests in bytes
     init_buf: Option<usize>,
 
+    /// The maximum size of request bodies in bytes
+    max_body_size: Option<usize>,
+
+    /// The maximum number of request headers
+    max_headers: Option<usize>,
+
+    /// The maximum length of the requested path
+    max_uri_length: Option<usize>,
+
+    /// The maximum number of segments in the requested path
+    max_path_segments: usize,
+
+    /// How long writing to a client may block before the connection is dropped
+    write_timeout: Option<Duration>,
+
+    /// How long connections may stay idle between requests, if keep-alive is enabled
+    keep_alive: Option<Duration>,
+
+    /// How long handlers have to finish requests, (see `Stream::deadline`)
+    request_timeout: Option<Duration>,
+
     /// If file caching should be enabled or not
     cache: Option<FileCacheType>,
 
+    /// Files which are never cached, by path or extension
+    cache_exclude: Vec<String>,
+
+    /// If cached files are stored gzip compressed
+    cache_compressed: bool,
+
+    /// How long missing static files are remembered, and how many
+    miss_cache: Option<(Duration, usize)>,
+
+    /// The type of files whose type can't be guessed
+    default_content_type: Option<ResponseType>,
+
+    /// The body format of error responses
+    error_format: ErrorFormat,
+
+    /// An html template which error responses are rendered with
+    error_page_template: Option<&'static str>,
+
+    /// What templates do with missing keys
+    missing_keys: MissingKeys,
+
+    /// How many requests a client (by ip address) can have being handled at once
+    per_ip_concurrency: Option<usize>,
+
+    /// The number of requests being handled for each client
+    active_requests: Arc<Mutex<HashMap<IpAddr, usize>>>,
+
+    /// How often idle connections are looked for and closed
+    connection_reaper: Option<Duration>,
+
+    /// The open connections, if the connection reaper is enabled
+    connections: Arc<Connections>,
+
     /// If server logging should be enabled (Like when caching files / opening server)
     logs: bool,
 
     /// If CORS should be enabled or not
     cors: bool,
+
+    /// If extensionless paths are served from html files in the static dir
+    clean_urls: bool,
+
+    /// If files and directories starting with a `.` are served from the static dir
+    serve_dotfiles: bool,
+
+    /// What requests to /favicon.ico which no route handles are responded to with
+    favicon: Option<Favicon>,
+
+    /// If TRACE requests should be echoed back to the client
+    trace: bool,
+
+    /// If POST requests can be routed as PUT, DELETE or PATCH
+    method_override: bool,
+
+    /// Creates the html body of redirects from the url
+    redirect_body: Option<fn(&str) -> String>,
+
+    /// Headers which are sent with every response
+    default_headers: Vec<(String, String)>,
+
+    /// Logs requests and responses, with secrets redacted
+    #[cfg(feature = "serde")]
+    audit_log: Option<audit::AuditConfig>,
+
+    /// If response header names are written in Title-Case
+    canonicalize_headers: bool,
+
+    /// How long in-flight requests get to finish when shutting down
+    shutdown_timeout: Duration,
+
+    /// How long connections which haven't been handled yet get to be
+    /// handled when shutting down, (until `shutdown_timeout` if None)
+    drain_timeout: Option<Duration>,
+
+    /// If request bodies are read before routing, or left for handlers to stream
+    body_mode: BodyMode,
+
+    /// Called before each request is dispatched
+    on_request_start: Option<fn(&Stream)>,
+
+    /// Called after each request, with the status and how long it took
+    on_request_end: Option<fn(&Stream, Option<u16>, Duration)>,
+
+    /// Takes over connections which ask to switch protocols
+    on_upgrade: Option<fn(&mut Stream, &str)>,
 }
 
 /// A quick way of nesting routes inside of eachother
@@ -103,423 +293,2349 @@ pub struct Server {
 ///             Route::Get("self-destruct", |stream| {})
 ///         ])
 ///     ]),
+///     Route::Method(Method::DELETE, "user", |stream| {}),
+///     Route::Any("echo", |stream| {}),
+/// ];
+/// ```
+///
+/// ## Url parameters
+/// Segments wrapped in colons capture the value of that segment, which
+/// can be read via `stream.params()`. A type can be added after the name
+/// (`i64`, `u64`, `f64` or `uuid`), and segments which don't parse as that
+/// type won't match the route, falling through to other routes (or 404).
+/// The last parameter can be made optional with a `?`, in which case it's
+/// missing from `stream.params()` when left out of the url.
+/// ```
+/// use responder::prelude::*;
+///
+/// let routes = &[
+///     Route::Get("day/:day:", |stream| {}),
+///     Route::Get("posts/:page?:u64:", |stream| {
+///         let page: u64 = stream.param_as("page").unwrap_or(1);
+///     }),
+///     Route::Get("users/:id:u64:", |stream| {
+///         let id: u64 = stream.param_as("id").unwrap();
+///     }),
 /// ];
 /// ```
+#[derive(Clone)]
 pub enum Route {
     /// A stack containing either an endpoint like Get or Post, or another Stack
     Stack(&'static str, &'static [Route]),
 
+    /// A stack with it's own cors config, which overrides the server-wide
+    /// cors setting for all routes inside of it. `None` disables cors.
+    CorsStack(Option<Cors>, &'static str, &'static [Route]),
+
+    /// A stack with it's own maximum body size in bytes, which overrides
+    /// `max_body_size` for all routes inside of it. Bodies which are too big
+    /// are responded to with `413 Payload Too Large` before the handler is
+    /// called. Buffered bodies are read before routing, so their limit can
+    /// only be lowered, while streamed bodies (see `BodyMode::Streaming`)
+    /// can be allowed to be bigger than `max_body_size`.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use responder::request::BodyMode;
+    ///
+    /// Server::new()
+    ///     .body_mode(BodyMode::Streaming)
+    ///     .max_body_size(64 * 1024)
+    ///     .routes(&[
+    ///         Route::LimitStack(1024, "auth", &[
+    ///             Route::Post("login", |stream| {}),
+    ///         ]),
+    ///         Route::LimitStack(512 * 1024 * 1024, "files", &[
+    ///             Route::Post("upload", |stream| {
+    ///                 stream.save_body_to("/tmp/upload.bin").ok();
+    ///             }),
+    ///         ]),
+    ///     ]);
+    /// ```
+    LimitStack(usize, &'static str, &'static [Route]),
+
     /// A stack with all it's routes protected by an origin control function.
     /// The origin control function returns a boolean indicating wether the
     /// request is valid or not. (true = continue the request. false = cancel)
     ControlledStack(fn(&mut Stream) -> bool, &'static str, &'static [Route]),
 
+    /// A stack which only matches requests to a specific host, (the `Host`
+    /// header without the port, compared case-insensitively). Doesn't add
+    /// to the path. Used for serving multiple sites on the same port.
+    ///
+    /// Wildcard hosts, (ex `*.example.com`) match any single-label subdomain,
+    /// which is available to the routes as the `subdomain` url parameter.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// let routes = &[
+    ///     Route::Host("api.example.com", &[
+    ///         Route::Get("users", |stream| {}),
+    ///     ]),
+    ///     Route::Host("www.example.com", &[
+    ///         Route::File("", "./static/index.html"),
+    ///     ]),
+    ///     Route::Host("*.example.com", &[
+    ///         Route::Get("", |stream| {
+    ///             let tenant: &str = stream.params()["subdomain"].as_str();
+    ///         }),
+    ///     ]),
+    /// ];
+    /// ```
+    Host(&'static str, &'static [Route]),
+
+    /// A stack which only matches requests accepting a media type, (using
+    /// the `Accept` header, where `*/*` and `type/*` ranges are supported).
+    /// Doesn't add to the path. Routes are tried in order, so the preferred
+    /// representation goes first. Requests to a path which no route can
+    /// produce an acceptable response for get `406 Not Acceptable`. Responses
+    /// are sent with `Vary: Accept`, so caches keep the representations apart.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// let routes = &[
+    ///     Route::Accept("text/html", &[
+    ///         Route::File("data", "./static/data.html"),
+    ///     ]),
+    ///     Route::Accept("application/json", &[
+    ///         Route::Get("data", |stream| stream.payload_status(200u16)),
+    ///     ]),
+    /// ];
+    /// ```
+    Accept(&'static str, &'static [Route]),
+
     /// Enpoint - Get request
     Get(&'static str, fn(&mut Stream) -> ()),
 
     /// Enpoint - Post request
     Post(&'static str, fn(&mut Stream) -> ()),
 
-    /// Enpoint - File serving
+    /// Enpoint - Request with any http-method
+    Any(&'static str, fn(&mut Stream) -> ()),
+
+    /// Enpoint - Request with a specific http-method, like PUT or DELETE
+    Method(Method, &'static str, fn(&mut Stream) -> ()),
+
+    /// Enpoint - File serving, for GET and HEAD requests
     File(&'static str, &'static str),
+
+    /// Handles requests to paths inside of the stack it's in, which no
+    /// other route in the stack matches. Lets route groups send their own
+    /// not found responses, instead of the server-wide one. Paths which
+    /// are routed, but not for the request's method, still get `405`.
+    /// Fallbacks which aren't inside of a stack are ignored, use
+    /// `Server::not_found` for the server-wide not found page.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// let routes = &[
+    ///     Route::ControlledStack(|stream| { true }, "api", &[
+    ///         Route::Get("users", |stream| {}),
+    ///         Route::Fallback(|stream| {
+    ///             stream.respond(404u16, Respond::new().json("{\"error\":\"no such endpoint\"}"));
+    ///         }),
+    ///     ]),
+    /// ];
+    /// ```
+    Fallback(fn(&mut Stream) -> ()),
+}
+
+/// A request to match against a route table using `match_route`. Only
+/// the parts of a request which routing depends on are needed.
+#[derive(Debug, Clone, Copy)]
+pub struct RouteRequest<'a> {
+    /// The http-method
+    pub method: Method,
+
+    /// The requested path
+    pub path: &'a str,
+
+    /// The requested host without the port, used by `Route::Host`
+    pub host: Option<&'a str>,
+
+    /// The `Accept` header, used by `Route::Accept`
+    pub accept: Option<&'a str>,
+}
+
+/// What a route table does with a request, see `match_route`
+pub enum MatchResult<'r> {
+    /// A route handles the request
+    Found(RouteMatch<'r>),
+
+    /// Routes match the path, but not the method. Contains the
+    /// methods which the path accepts, (sent in the `Allow` header)
+    MethodNotAllowed(Vec<&'static str>),
+
+    /// Routes match the path, but not the `Accept` header
+    NotAcceptable,
+
+    /// No route matches the path
+    NotFound,
+}
+
+/// A route which handles a request, see `match_route`
+pub struct RouteMatch<'r> {
+    /// The endpoint or file route
+    pub route: &'r Route,
+
+    /// The path of the route, including the paths of the
+    /// stacks it's in, (see `Stream::route_template`)
+    pub template: String,
+
+    /// The url parameters
+    pub params: HashMap<String, String>,
+
+    /// If the request is a cors preflight, which is
+    /// answered by the server instead of the route
+    pub preflight: bool,
+
+    /// The cors config of the innermost CorsStack the route is in
+    cors: Option<Option<Cors>>,
+
+    /// The maximum body size of the innermost LimitStack the route is in
+    max_body: Option<usize>,
+
+    /// If the route is in an Accept stack, so the response depends on the Accept header
+    negotiated: bool,
+}
+
+impl<'a> RouteRequest<'a> {
+    /// A request without a `Host` or `Accept` header
+    pub fn new(method: Method, path: &'a str) -> Self {
+        RouteRequest {
+            method,
+            path,
+            host: None,
+            accept: None,
+        }
+    }
 }
 
 /*- Functions -*/
-fn handle_req(tcp_stream: TcpStream, config: &Server) {
-    /*- Data buffer -*/
+/*- Handle all requests on a connection. Without keep-alive, that's
+only one. With keep-alive, requests are handled until the client
+closes the connection, or stays idle for too long -*/
+fn handle_connection(tcp_stream: TcpStream, config: &Server) {
+    /*- A zero duration would be an error, so those are ignored -*/
+    if let Some(timeout) = config.write_timeout {
+        tcp_stream.set_write_timeout(Some(timeout)).ok();
+    };
+
+    /*- The reaper closes the connection if it waits too long for a request -*/
+    let watched: Option<WatchedConnection> = match config.connection_reaper {
+        Some(_) => WatchedConnection::watch(&config.connections, &tcp_stream),
+        None => None,
+    };
+
+    /*- Bytes recieved after the end of a request, (pipelined requests)
+    which are the beginning of the next one -*/
+    let mut pending: Vec<u8> = Vec::new();
+    loop {
+        let stream_inner: TcpStream = match tcp_stream.try_clone() {
+            Ok(e) => e,
+            Err(_) => return,
+        };
+        if !handle_req(stream_inner, config, &mut pending, watched.as_ref()) {
+            return;
+        };
+        if let Some(watched) = &watched {
+            watched.set_waiting(true);
+        };
+
+        /*- Close connections which stay idle between requests -*/
+        tcp_stream.set_read_timeout(config.keep_alive).ok();
+    }
+}
+
+/*- Handle one request. Returns true if the connection can be reused -*/
+fn handle_req(
+    tcp_stream: TcpStream,
+    config: &Server,
+    pending: &mut Vec<u8>,
+    watched: Option<&WatchedConnection>,
+) -> bool {
+    let peer: Option<IpAddr> = tcp_stream.peer_addr().ok().map(|addr| addr.ip());
+
+    /*- Data buffer, starting with what was left over from the last request -*/
     let buffer: &mut Vec<u8> = &mut vec![0u8; config.init_buf.unwrap_or(DATA_BUF_POST_INIT)];
+    if pending.len() > buffer.len() {
+        buffer.resize(pending.len(), 0);
+    };
+    let mut recieved: usize = pending.len();
+    buffer[..recieved].copy_from_slice(pending);
+    pending.clear();
+
+    /*- The stream records when it was created, which is used for `stream.elapsed()` -*/
     let mut stream = Stream::from(tcp_stream);
 
     /*- Set CORS -*/
     if config.cors {
         stream.enable_cors();
     };
+    stream.set_logs(config.logs);
+    stream.set_redirect_body(config.redirect_body);
+    stream.set_default_content_type(config.default_content_type);
+    stream.set_error_format(config.error_format);
+    stream.set_error_page_template(config.error_page_template);
+    stream.set_missing_keys(config.missing_keys);
+    stream.set_deadline(config.request_timeout);
+    stream.set_default_headers(&config.default_headers);
+    stream.set_canonicalize_headers(config.canonicalize_headers);
+    #[cfg(feature = "serde")]
+    if let Some(audit_log) = config.audit_log {
+        /*- One byte more than what's logged, so it's known if it was truncated -*/
+        stream.set_capture_limit(audit_log.max_body.saturating_add(1));
+    };
 
-    /*- Read data into buffer -*/
-    match stream.get_mut_inner_ref().read(buffer) {
-        Ok(data) => data,
-        Err(_) => return,
+    /*- Read data into buffer until we've recieved all headers. If
+    they don't fit into the buffer, we won't be able to parse them -*/
+    let header_end: usize = loop {
+        if let Some(pos) = utils::headers::find_header_end(&buffer[..recieved]) {
+            break pos;
+        } else if recieved == buffer.len() {
+            stream.respond_status(431u16);
+            return false;
+        };
+
+        match stream.read_inner(&mut buffer[recieved..]) {
+            /*- The client closed the connection, either between requests or
+            before sending all headers. Neither is a request worth parsing, and
+            nobody is left to read a response -*/
+            Ok(0) => return false,
+            Ok(data) => recieved += data,
+            Err(_) => return false,
+        };
+    };
+    if let Some(watched) = watched {
+        watched.set_waiting(false);
+    };
+
+    /*- Clients can only have a limited number of requests being handled at once,
+    (connections waiting for their next request don't count) -*/
+    let _active: Option<ActiveRequest> = match (config.per_ip_concurrency, peer) {
+        (Some(max), Some(ip)) => match ActiveRequest::start(&config.active_requests, ip, max) {
+            Some(e) => Some(e),
+            None => {
+                stream.respond_status(429u16);
+                return false;
+            }
+        },
+        _ => None,
     };
 
     /*- Parse headers (via utils) -*/
-    let request: String = String::from_utf8_lossy(
-        // Remove empty bytes
-        &buffer[..buffer.iter().position(|&r| r == 0).unwrap_or(buffer.len())],
-    )
-    .to_string();
-    let headers: HashMap<&str, &str> = utils::headers::parse_headers(&request);
+    let request: String = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let headers: Vec<(&str, &str)> =
+        match utils::headers::parse_headers(&request, config.max_headers.unwrap_or(usize::MAX)) {
+            Some(e) => e,
+            None => {
+                stream.respond_status(431u16);
+                return false;
+            }
+        };
 
     /*- Get request info -*/
-    let mut body: String = String::new();
-    let info: RequestInfo = match RequestInfo::parse_req(&request) {
+    let mut info: RequestInfo = match RequestInfo::parse_req(&request) {
         Ok(e) => e,
-        Err(_) => return,
+        Err(_) => return false,
+    };
+    if config
+        .max_uri_length
+        .is_some_and(|max| info.path.len() + info.query.len() > max)
+    {
+        stream.respond_status(414u16);
+        return false;
     };
 
-    /*- POST requests often contain huge bodies in terms of bytes, (ex when sending images). The
-    DATA_BUF_INIT constant is regularly set to a relativly small number like 2048 which images
-    won't fit into, therefore we'll update the buffer array to contain more bytes for POST requests -*/
-    if info.method == Method::POST {
-        body = request.split("\r\n\r\n").last().unwrap_or("").to_string();
-        // TODO
-    }
-    let mut full_path: String = String::new();
-    stream.set_body(body);
-    stream.set_headers(headers);
+    /*- Chunked bodies are decoded, other transfer codings aren't supported -*/
+    let chunked: bool = headers.iter().any(|(key, value)| {
+        key.eq_ignore_ascii_case("transfer-encoding")
+            && value
+                .rsplit(',')
+                .next()
+                .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+    });
+
+    /*- Content-Length can be sent more than once, (or as a list) but only with the
+    same value. Other values can't be framed, and would let the body be read as
+    the next request, so they're refused -*/
+    let content_length: Option<usize> = match parse_content_length(&headers) {
+        Ok(e) => e,
+        Err(_) => {
+            stream.respond_status(400u16);
+            return false;
+        }
+    };
 
-    /*- Get the function or file which is coupled to the request path -*/
-    for route in config.routes {
-        match call_endpoint(&route, info, &mut full_path, &mut stream) {
-            Ok(_) => return,
-            Err(optional_status) => {
-                if let Some(status) = optional_status {
-                    return stream.respond_status(status);
-                }
+    /*- HTTP/1.1 connections are persistent unless the client says otherwise, and
+    HTTP/1.0 connections only if the client asks for it. Bodies in other transfer
+    codings can't be framed, so those connections are closed. So are connections
+    with both Transfer-Encoding and Content-Length, as the client and proxies in
+    between might disagree on where the body ends -*/
+    let connection_has = |token: &str| {
+        headers.iter().any(|(key, value)| {
+            key.eq_ignore_ascii_case("connection")
+                && value
+                    .split(',')
+                    .any(|value| value.trim().eq_ignore_ascii_case(token))
+        })
+    };
+
+    /*- Upgrade requests leave the request cycle, so the
+    connection isn't kept alive for another request -*/
+    let upgrade: Option<&str> = match config.on_upgrade {
+        Some(_) if info.version == "HTTP/1.1" && connection_has("upgrade") => headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("upgrade"))
+            .map(|(_, value)| value.trim()),
+        _ => None,
+    };
+    let keep_alive: bool = config.keep_alive.is_some()
+        && upgrade.is_none()
+        && match info.version {
+            "HTTP/1.1" => !connection_has("close"),
+            "HTTP/1.0" => connection_has("keep-alive"),
+            _ => false,
+        }
+        && !headers
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case("transfer-encoding") && !chunked)
+        && !(chunked && content_length.is_some());
+
+    /*- Bodies often contain more bytes than what fits into the buffer, (ex when sending
+    images). If Content-Length says there's more to come, we'll keep reading until
+    we've got all of it, instead of cutting the body off at the buffer size -*/
+    let mut body_buf: Vec<u8> = buffer[header_end..recieved].to_vec();
+    let streaming: bool = config.body_mode == BodyMode::Streaming
+        && (chunked || content_length.is_some_and(|e| e > 0));
+    if streaming {
+        /*- The body is left for the handler to read. Its size is checked
+        once the route is matched, as LimitStacks can allow bigger bodies -*/
+        let framing: Framing = match content_length {
+            _ if chunked => Framing::Chunked(0, true),
+            Some(content_length) => {
+                if body_buf.len() > content_length {
+                    pending.extend_from_slice(&body_buf[content_length..]);
+                    body_buf.truncate(content_length);
+                };
+                Framing::Length(content_length as u64)
             }
+            None => Framing::Done,
         };
-    }
-
-    /*- If no path was found, we'll check if the
-    user want's to serve any static dirs -*/
-    if let Some(static_path) = config.serve {
-        match serve_static_dir(static_path, info.path, &mut stream) {
-            Ok(_) => (),
-            Err(_) => {
-                /*- Now that we didn't find a function, nor
-                a static file, we'll send a 404 page -*/
-                not_found(&mut stream, *config);
+        stream.set_unread_body(std::mem::take(&mut body_buf), framing, config.max_body_size);
+    } else if chunked {
+        /*- Transfer-Encoding overrides Content-Length -*/
+        let mut trailers: Vec<(String, String)> = Vec::new();
+        body_buf = match read_chunked_body(
+            &mut stream,
+            buffer,
+            body_buf,
+            config.max_body_size,
+            pending,
+            &mut trailers,
+        ) {
+            Ok(e) => e,
+            Err(status) => {
+                stream.respond_status(status);
+                return false;
             }
         };
-    } else {
-        not_found(&mut stream, *config);
-    };
-}
-
-/*- Execute an api function depending on path -*/
-fn call_endpoint(
-    routes: &Route,
-    info: RequestInfo,
-    full_path: &mut String,
-
-    /*- Function parameters -*/
-    stream: &mut Stream,
-) -> Result<(), Option<u16>> {
-    /*- ControlledStack and Stack have similar functionality,
-    the diffrence is that ControlledStack needs origin
-    control funciton to be called in the beginning -*/
-    if let Route::ControlledStack(_, pathname, next_routes) | Route::Stack(pathname, next_routes) =
-        routes
-    {
-        if let Route::ControlledStack(fnc, _, _) = routes {
-            /*- If request didn't pass origin control filters,
-            return with no error code because response
-            are handled in origin control function -*/
-            if fnc(stream) == false {
-                return Err(None);
-            };
-        }
+        stream.set_trailers(trailers);
+    } else if let Some(content_length) = content_length {
+        if config.max_body_size.is_some_and(|max| content_length > max) {
+            stream.respond_status(413u16);
+            return false;
+        };
 
-        /*- If a tail was found -*/
-        let mut tail_found: bool = false;
-
-        /*- Iterate over all stacks and tails -*/
-        'tail_search: for route in next_routes.iter() {
-            /*- Push the path -*/
-            let mut possible_full_path = full_path.clone();
-            possible_full_path.push_str(pathname);
-            possible_full_path.push('/');
-
-            /*- Recurse -*/
-            match call_endpoint(route, info, &mut possible_full_path, stream) {
-                Ok(_) => {
-                    tail_found = true;
-
-                    /*- Push the path to the actual final path -*/
-                    full_path.push_str(pathname);
-                    full_path.push('/');
-                    break 'tail_search;
+        while body_buf.len() < content_length {
+            match stream.read_inner(buffer) {
+                Ok(0) => {
+                    stream.respond_status(400u16);
+                    return false;
                 }
-                Err(_) => continue,
+                Ok(data) => body_buf.extend_from_slice(&buffer[..data]),
+                Err(_) => return false,
             };
         }
+        pending.extend_from_slice(&body_buf[content_length..]);
+        body_buf.truncate(content_length);
+    } else if keep_alive {
+        /*- Without Content-Length there's no body, so
+        the rest belongs to the next request -*/
+        pending.append(&mut body_buf);
+    };
 
-        /*- Return -*/
-        if tail_found {
-            return Ok(());
-        } else {
-            return Err(None);
-        }
-    }
-
-    /*- Check what type of route it is -*/
-    match routes {
-        Route::Post(pathname, function_ptr) | Route::Get(pathname, function_ptr) => {
-            /*- Store url parameters. An url parameter is a "variable" which
-            will be set in the url. Example: localhost:8000/day/:day: -*/
-            let mut params: HashMap<String, String> = HashMap::new();
+    /*- Compressed bodies are decoded, so handlers always see the plain body -*/
+    #[cfg(feature = "gzip")]
+    if let Some((_, content_encoding)) = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-encoding"))
+        .filter(|_| !streaming)
+    {
+        let max: usize = config.max_body_size.unwrap_or(DECODED_BODY_MAX);
+        body_buf = match utils::encoding::decode_body(content_encoding, &body_buf, max) {
+            Ok(e) => e,
+            Err(status) => {
+                stream.respond_status(status);
+                return false;
+            }
+        };
+    };
+    let body: String = String::from_utf8_lossy(&body_buf).to_string();
 
-            /*- Push the pathname -*/
-            let mut possible_full_path = full_path.clone();
-            possible_full_path.push_str(pathname);
+    /*- Clients which can only send POST requests, (like html forms)
+    may ask for another method to be used when routing -*/
+    if config.method_override && info.method == Method::POST {
+        if let Some(method) = method_override(&headers, &body) {
+            info.method = method;
+        };
+    };
+    stream.set_raw_body(body_buf);
 
-            /*- Check for url parameters -*/
-            let final_subpaths: Vec<&str> = get_subpaths(&possible_full_path);
-            let mut final_check_url: String = possible_full_path.clone();
+    stream.set_body(body);
+    stream.set_header_list(headers);
+    stream.set_raw_request(&request);
+    stream.set_info(info);
+    if keep_alive {
+        stream.enable_keep_alive();
+    };
 
-            /*- Iterate and find url params -*/
-            for (index, request_path) in get_subpaths(info.path).iter().enumerate() {
-                /*- Get the current searched subpath to check wether they are the same -*/
-                let subp: &str = match final_subpaths.get(index) {
-                    Some(e) => e,
-                    None => return Err(None),
-                };
+    if let Some(on_request_start) = config.on_request_start {
+        on_request_start(&stream);
+    };
+    match (config.on_upgrade, upgrade) {
+        (Some(on_upgrade), Some(protocol)) => on_upgrade(&mut stream, protocol),
+        _ => dispatch(&mut stream, config, info, &request),
+    };
+    if let Some(on_request_end) = config.on_request_end {
+        on_request_end(&stream, stream.status(), stream.elapsed());
+    };
+    #[cfg(feature = "serde")]
+    if let Some(audit_log) = &config.audit_log {
+        audit::log(&stream, audit_log);
+    };
 
-                match is_url_param(subp) {
-                    Some(param_name) => {
-                        params.insert(param_name.into(), request_path.to_string());
+    /*- Streamed bodies which weren't read would be mistaken for the next request -*/
+    if !stream.finish_body(pending) {
+        return false;
+    };
 
-                        /*- Change full_path -*/
-                        final_check_url = final_check_url.replace(subp, request_path);
-                        continue;
-                    }
-                    None => {
-                        if request_path != &subp {
-                            return Err(None);
-                        } else {
-                            continue;
-                        };
-                    }
-                }
-            }
+    /*- Responses which weren't sent completely would confuse the next one -*/
+    stream.keep_alive() && stream.responded() && !stream.disconnected()
+}
 
-            /*- If it's the requested path -*/
-            if trim(final_check_url) == trim(info.path.to_string()) {
-                /*- If it's the requested method -*/
-                match &info.method {
-                    Method::GET => {
-                        /*- Call the associated function -*/
-                        stream.set_params(params);
-                        function_ptr(stream);
-
-                        /*- Return success -*/
-                        Ok(())
-                    }
-                    Method::POST => {
-                        /*- Call the associated function -*/
-                        stream.set_params(params);
-                        function_ptr(stream);
+/*- Get the method from the X-HTTP-Method-Override header, or the _method field of
+a form. Only methods which html forms can't send are allowed, so a POST can't
+be turned into a GET (which might skip checks meant for state changing requests) -*/
+fn method_override(headers: &[(&str, &str)], body: &str) -> Option<Method> {
+    let is_form: bool = headers.iter().any(|(key, value)| {
+        key.eq_ignore_ascii_case("content-type")
+            && value
+                .trim_start()
+                .to_ascii_lowercase()
+                .starts_with("application/x-www-form-urlencoded")
+    });
+    let method: &str = match headers
+        .iter()
+        .rev()
+        .find(|(key, _)| key.eq_ignore_ascii_case("x-http-method-override"))
+    {
+        Some((_, value)) => value.trim(),
+        None if is_form => body
+            .split('&')
+            .find_map(|field| field.strip_prefix("_method="))?,
+        None => return None,
+    };
 
-                        /*- Return success -*/
-                        Ok(())
-                    }
-                    Method::UNKNOWN => Err(Some(405u16)), // Method not allowed
-                    Method::OPTIONS => {
-                        stream.respond_status(200u16);
-                        Ok(())
-                    }
-                    _ => {
-                        /*- Call the associated function -*/
-                        stream.set_params(params);
-                        function_ptr(stream);
+    match &*method.to_ascii_uppercase() {
+        "PUT" => Some(Method::PUT),
+        "DELETE" => Some(Method::DELETE),
+        "PATCH" => Some(Method::PATCH),
+        _ => None,
+    }
+}
 
-                        /*- Return success -*/
-                        Ok(())
-                    }
-                }
-            } else {
-                Err(None)
+/*- Decode a chunked request body, reading more from the stream until the last
+chunk has been recieved. Chunk extensions are skipped, trailers are collected, and
+anything after the body is left in `pending`. Bodies are limited to `max_body_size`,
+or `CHUNKED_BODY_MAX` if it isn't set. Returns the status to respond with on errors -*/
+fn read_chunked_body(
+    stream: &mut Stream,
+    buffer: &mut [u8],
+    mut data: Vec<u8>,
+    max_body_size: Option<usize>,
+    pending: &mut Vec<u8>,
+    trailers: &mut Vec<(String, String)>,
+) -> Result<Vec<u8>, u16> {
+    let mut body: Vec<u8> = Vec::new();
+    let mut pos: usize = 0;
+    let mut last_chunk: bool = false;
+    let mut trailer_size: usize = 0;
+
+    loop {
+        /*- Every chunk starts with its size, and the last one is followed by trailers -*/
+        let line_end: usize = match data[pos..].windows(2).position(|window| window == b"\r\n") {
+            Some(e) => pos + e,
+            None if data.len() - pos > CHUNK_LINE_MAX => return Err(400u16),
+            None => {
+                read_more(stream, buffer, &mut data)?;
+                continue;
             }
+        };
+        let line: &[u8] = &data[pos..line_end];
+        pos = line_end + 2;
+
+        /*- An empty line ends the trailers, and the body -*/
+        if last_chunk {
+            if line.is_empty() {
+                pending.extend_from_slice(&data[pos..]);
+                return Ok(body);
+            };
+            trailer_size += line.len() + 2;
+            if trailer_size > TRAILERS_MAX {
+                return Err(431u16);
+            };
+            if let Some((key, value)) = String::from_utf8_lossy(line).split_once(':') {
+                trailers.push((key.to_string(), value.trim().to_string()));
+            };
+            continue;
+        };
+
+        let size: usize = match parse_chunk_size(line).map(usize::try_from) {
+            Some(Ok(e)) => e,
+            _ => return Err(400u16),
+        };
+        if size == 0 {
+            last_chunk = true;
+            continue;
+        };
+        if body.len().saturating_add(size) > max_body_size.unwrap_or(CHUNKED_BODY_MAX) {
+            return Err(413u16);
+        };
+
+        /*- The chunk data is followed by a CRLF -*/
+        let end: usize = match pos.checked_add(size).and_then(|e| e.checked_add(2)) {
+            Some(e) => e,
+            None => return Err(400u16),
+        };
+        while data.len() < end {
+            read_more(stream, buffer, &mut data)?;
         }
-        Route::File(endpoint_path, file_path) => {
-            println!("{endpoint_path} {file_path}");
-            /*- Push the pathname -*/
-            let mut possible_full_path = full_path.clone();
-            possible_full_path.push_str(&endpoint_path);
-
-            dbg!(&possible_full_path);
-            dbg!(info.path);
-
-            if trim(possible_full_path) == trim(info.path.to_string()) {
-                stream.respond_file(200u16, file_path);
-                Ok(())
-            } else {
-                Err(None)
-            }
+        if &data[end - 2..end] != b"\r\n" {
+            return Err(400u16);
+        };
+        body.extend_from_slice(&data[pos..end - 2]);
+        pos = end;
+
+        /*- Forget about chunks which have been decoded already -*/
+        data.drain(..pos);
+        pos = 0;
+    }
+}
+
+/*- The value of the Content-Length headers, which have to be digits only, and
+all have the same value. Returns Err if the body length can't be decided -*/
+fn parse_content_length(headers: &[(&str, &str)]) -> Result<Option<usize>, ()> {
+    let mut content_length: Option<usize> = None;
+    for (_, value) in headers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+    {
+        for value in value.split(',').map(str::trim) {
+            if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(());
+            };
+            let length: usize = value.parse::<usize>().map_err(|_| ())?;
+            if content_length.is_some_and(|e| e != length) {
+                return Err(());
+            };
+            content_length = Some(length);
         }
-        _ => Err(Some(405u16)), // Method not allowed
     }
+
+    Ok(content_length)
 }
 
-/*- Trim paths with trailing and leading slashes -*/
-pub fn trim(input: String) -> String {
-    let mut output = input.clone();
-    if output.ends_with('/') {
-        output.pop();
-    };
-    if output.starts_with('/') {
-        output.remove(0);
+/*- The size of a chunk is written in hex, optionally followed by ;extensions.
+Anything else than hex digits, (like signs, or sizes too big for a u64) is invalid -*/
+pub(crate) fn parse_chunk_size(line: &[u8]) -> Option<u64> {
+    let size: &str = std::str::from_utf8(line).ok()?.split(';').next()?.trim();
+    if size.is_empty() || !size.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
     };
-    output
+    u64::from_str_radix(size, 16).ok()
 }
 
-/*- Get subpaths of a full path. Example: get_subpaths("/Path/to/value") -> vec!["Path", "to", "value"] -*/
-fn get_subpaths(path: &str) -> Vec<&str> {
-    let mut subpaths: Vec<&str> = Vec::new();
+/*- Read more data from the stream, for bodies which haven't been recieved completely -*/
+fn read_more(stream: &mut Stream, buffer: &mut [u8], data: &mut Vec<u8>) -> Result<(), u16> {
+    match stream.read_inner(buffer) {
+        Ok(0) | Err(_) => Err(400u16),
+        Ok(read) => {
+            data.extend_from_slice(&buffer[..read]);
+            Ok(())
+        }
+    }
+}
 
-    /*- Iterate over all subpaths -*/
-    for subpath in path.split('/') {
-        if !subpath.is_empty() {
-            subpaths.push(subpath);
-        };
+/*- Connections watched by the reaper, by peer address. The instant is when the
+connection started waiting for its next request, and None while one is handled -*/
+type Connections = Mutex<HashMap<SocketAddr, (TcpStream, Option<Instant>)>>;
+
+/*- A connection which is watched by the reaper until it's dropped -*/
+struct WatchedConnection<'a> {
+    connections: &'a Connections,
+    peer: SocketAddr,
+}
+
+impl<'a> WatchedConnection<'a> {
+    /*- Start watching a connection, which is waiting for its first request -*/
+    fn watch(connections: &'a Connections, tcp_stream: &TcpStream) -> Option<Self> {
+        let peer: SocketAddr = tcp_stream.peer_addr().ok()?;
+        let tcp_stream: TcpStream = tcp_stream.try_clone().ok()?;
+        connections
+            .lock()
+            .ok()?
+            .insert(peer, (tcp_stream, Some(Instant::now())));
+        Some(WatchedConnection { connections, peer })
     }
 
-    /*- Return -*/
-    subpaths
+    /*- Mark the connection as waiting for a request since now, or as busy -*/
+    fn set_waiting(&self, waiting: bool) {
+        if let Ok(mut connections) = self.connections.lock() {
+            if let Some((_, since)) = connections.get_mut(&self.peer) {
+                *since = waiting.then(Instant::now);
+            };
+        };
+    }
 }
 
-/*- Check if a path is a url parameter -*/
-fn is_url_param(path: &str) -> Option<&str> {
-    if path.starts_with(':') && path.ends_with(':') {
-        Some(&path[1..path.len() - 1])
-    } else {
-        None
+impl Drop for WatchedConnection<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut connections) = self.connections.lock() {
+            connections.remove(&self.peer);
+        };
     }
 }
 
-/*- Serve static files from a specified dir -*/
-fn serve_static_dir(dir: &str, request_path: &str, stream: &mut Stream) -> Result<(), ()> {
+/*- Close connections which have been waiting for a request for longer than
+`timeout`. Their workers are blocked reading, which then stops, freeing them -*/
+fn reap_connections(connections: &Connections, timeout: Duration) {
+    if let Ok(connections) = connections.lock() {
+        for (tcp_stream, since) in connections.values() {
+            if since.is_some_and(|since| since.elapsed() > timeout) {
+                tcp_stream.shutdown(std::net::Shutdown::Both).ok();
+            };
+        }
+    };
+}
+
+/*- A request which is being handled, counted towards
+the limit of its client until it's dropped -*/
+struct ActiveRequest<'a> {
+    active: &'a Mutex<HashMap<IpAddr, usize>>,
+    ip: IpAddr,
+}
+impl<'a> ActiveRequest<'a> {
+    /*- Returns None if the client already has `max` requests being handled -*/
+    fn start(active: &'a Mutex<HashMap<IpAddr, usize>>, ip: IpAddr, max: usize) -> Option<Self> {
+        let mut counts = active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let count: &mut usize = counts.entry(ip).or_insert(0);
+        if *count >= max {
+            return None;
+        };
+        *count += 1;
+
+        Some(ActiveRequest { active, ip })
+    }
+}
+impl Drop for ActiveRequest<'_> {
+    fn drop(&mut self) {
+        let mut counts = self
+            .active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(count) = counts.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.ip);
+            };
+        };
+    }
+}
+
+/*- Respond to a request, using the routes, static files or 404 page -*/
+fn dispatch(stream: &mut Stream, config: &Server, info: RequestInfo, request: &str) {
+    /*- No route can match paths deeper than the limit, (and
+    counting stops there, so long paths aren't split up) -*/
+    if info
+        .path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .nth(config.max_path_segments)
+        .is_some()
+    {
+        return not_found(stream, config);
+    };
+
+    /*- The host and Accept header are copied, so that
+    origin control functions can borrow the stream -*/
+    let host: Option<String> = stream.host().map(str::to_string);
+    let accept: Option<String> = stream.header("Accept").map(str::to_string);
+    let route_request: RouteRequest = RouteRequest {
+        method: info.method,
+        path: info.path,
+        host: host.as_deref(),
+        accept: accept.as_deref(),
+    };
+
+    /*- Asterisk-form OPTIONS requests, (OPTIONS * HTTP/1.1) ask
+    about the whole server, instead of a specific path -*/
+    if info.method == Method::OPTIONS && info.path == "*" {
+        let mut allowed: Vec<&str> = Vec::new();
+        allowed_methods(&config.routes, "", &route_request, false, &mut allowed);
+        allowed.push(Method::OPTIONS.as_str());
+        return stream.respond(
+            200u16,
+            Respond::new()
+                .text("")
+                .headers(vec![format!("Allow: {}", allowed.join(", "))]),
+        );
+    };
+
+    /*- TRACE requests are echoed back if enabled -*/
+    if info.method == Method::TRACE && config.trace {
+        return stream.respond(
+            200u16,
+            Respond::new().content(request, ResponseType::Custom("message/http")),
+        );
+    };
+
+    /*- Get the function or file which is coupled to the request path -*/
+    let result: MatchResult =
+        match_route_with(&config.routes, &route_request, &mut |route, cors| {
+            match route {
+                /*- Origin control functions respond themselves if the request
+                is cancelled, using the cors config of the stack they're in -*/
+                Route::ControlledStack(fnc, _, _) => {
+                    let previous_cors: Option<Cors> = stream.cors();
+                    if let Some(cors) = cors {
+                        stream.set_cors(cors);
+                    };
+                    let passed: bool = fnc(stream);
+                    stream.set_cors(previous_cors);
+                    passed
+                }
+                _ => true,
+            }
+        });
+    match result {
+        MatchResult::Found(found) => {
+            /*- Bodies bigger than the route allows are refused before the handler is called -*/
+            if !stream.limit_body(found.max_body.or(config.max_body_size)) {
+                return stream.respond_status(413u16);
+            };
+
+            /*- Responses of Accept stacks depend on the Accept header -*/
+            if found.negotiated {
+                stream.add_vary("Accept");
+            };
+            return call_endpoint(found, stream);
+        }
+
+        /*- The path exists, but the method is wrong, so
+        we list the methods which the path accepts -*/
+        MatchResult::MethodNotAllowed(allowed) => {
+            return stream.respond(
+                405u16,
+                Respond::new().headers(vec![format!("Allow: {}", allowed.join(", "))]),
+            );
+        }
+
+        /*- The server can't respond in a format the client accepts -*/
+        MatchResult::NotAcceptable => {
+            stream.add_vary("Accept");
+            return stream.respond_status(406u16);
+        }
+        MatchResult::NotFound => (),
+    };
+
+    /*- Browsers request favicons by themselves -*/
+    if let Some(favicon) = config.favicon {
+        if trim(info.path.to_string()) == "favicon.ico"
+            && matches!(info.method, Method::GET | Method::HEAD)
+        {
+            return serve_favicon(favicon, stream);
+        };
+    };
+
+    /*- If no path was found, we'll check if the
+    user want's to serve any embedded files, or static dirs -*/
+    if let Some((path, bytes)) = config.embedded.get_key_value(&trim(info.path.to_string())) {
+        let mut file = std::io::Cursor::new(bytes);
+        let response_type: ResponseType = stream.file_type(Path::new(path), &mut file);
+        stream.respond_file_reader(200u16, response_type, bytes.len() as u64, None, file);
+    } else if let Some(static_path) = config.serve {
+        match serve_static_dir(
+            static_path,
+            info.path,
+            config.miss_cache,
+            config.clean_urls,
+            config.serve_dotfiles,
+            stream,
+        ) {
+            Ok(_) => (),
+            Err(_) => {
+                /*- Now that we didn't find a function, nor
+                a static file, we'll send a 404 page -*/
+                not_found(stream, config);
+            }
+        };
+    } else {
+        not_found(stream, config);
+    };
+}
+
+/// Find the route in a route table which handles a request, without
+/// calling it. If no route does, the result tells why, (which decides
+/// between 405, 406 and 404). Useful for testing routes, as no server
+/// or socket is needed. Origin control functions of controlled stacks
+/// need a stream to be called with, so they are assumed to pass.
+///
+/// ## Example
+/// ```
+/// use responder::prelude::*;
+/// use responder::{match_route, MatchResult, RouteRequest};
+///
+/// let routes = &[Route::Stack("users", &[
+///     Route::Get(":id:u64:", |stream| {}),
+/// ])];
+///
+/// match match_route(routes, &RouteRequest::new(Method::GET, "/users/12")) {
+///     MatchResult::Found(found) => {
+///         assert_eq!(found.template, "users/:id:u64:");
+///         assert_eq!(found.params["id"], "12");
+///     }
+///     _ => panic!("route didn't match"),
+/// };
+/// assert!(matches!(
+///     match_route(routes, &RouteRequest::new(Method::GET, "/users/john")),
+///     MatchResult::NotFound
+/// ));
+/// ```
+pub fn match_route<'r>(routes: &'r [Route], request: &RouteRequest) -> MatchResult<'r> {
+    match_route_with(routes, request, &mut |_, _| true)
+}
+
+/*- Match a request against a route table. `enter` is called for every
+ControlledStack which is reached, (in the order the routes are tried)
+with the cors config of the CorsStack it's in, if any. Stacks are
+skipped if `enter` returns false -*/
+fn match_route_with<'r>(
+    routes: &'r [Route],
+    request: &RouteRequest,
+    enter: &mut dyn FnMut(&Route, Option<Option<Cors>>) -> bool,
+) -> MatchResult<'r> {
+    if let Some(found) = find_in(routes, request, "", None, enter) {
+        return MatchResult::Found(found);
+    };
+
+    /*- No route handled the request. If any route in the whole table
+    matches the path, it's the method that's wrong -*/
+    let mut allowed: Vec<&'static str> = Vec::new();
+    allowed_methods(routes, "", request, false, &mut allowed);
+    if !allowed.is_empty() {
+        allowed.push(Method::OPTIONS.as_str());
+        return MatchResult::MethodNotAllowed(allowed);
+    };
+
+    /*- Routes which match the path but not the Accept header -*/
+    allowed_methods(routes, "", request, true, &mut allowed);
+    if !allowed.is_empty() {
+        return MatchResult::NotAcceptable;
+    };
+
+    MatchResult::NotFound
+}
+
+/*- Find the endpoint or file route which handles a request. `prefix`
+is the path of the stacks which the route is in, and `cors` the cors
+config of the innermost CorsStack, if the route is in one -*/
+fn find_route<'r>(
+    route: &'r Route,
+    request: &RouteRequest,
+    prefix: &str,
+    cors: Option<Option<Cors>>,
+    enter: &mut dyn FnMut(&Route, Option<Option<Cors>>) -> bool,
+) -> Option<RouteMatch<'r>> {
+    match route {
+        /*- Host stacks only contain routes for requests to their host,
+        and Accept stacks for requests accepting their media type -*/
+        Route::Host(hostname, next_routes) => {
+            if !host_matches(hostname, request.host) {
+                return None;
+            };
+            let mut found: RouteMatch = find_in(next_routes, request, prefix, cors, enter)?;
+
+            /*- Url parameters with the same name take precedence -*/
+            if let Some(subdomain) = request
+                .host
+                .and_then(|host| wildcard_subdomain(hostname, host))
+            {
+                found
+                    .params
+                    .entry("subdomain".into())
+                    .or_insert(subdomain.to_string());
+            };
+            Some(found)
+        }
+        Route::Accept(_, next_routes) => {
+            if !accept_matches(route, request, false) {
+                return None;
+            };
+            let mut found: RouteMatch = find_in(next_routes, request, prefix, cors, enter)?;
+            found.negotiated = true;
+            Some(found)
+        }
+
+        /*- ControlledStack and Stack have similar functionality,
+        the diffrence is that ControlledStack needs origin
+        control funciton to be called in the beginning -*/
+        Route::Stack(pathname, next_routes)
+        | Route::CorsStack(_, pathname, next_routes)
+        | Route::LimitStack(_, pathname, next_routes)
+        | Route::ControlledStack(_, pathname, next_routes) => {
+            /*- CorsStacks override the cors config of the routes inside of it -*/
+            let cors: Option<Option<Cors>> = match route {
+                Route::CorsStack(stack_cors, _, _) => Some(*stack_cors),
+                _ => cors,
+            };
+            if let Route::ControlledStack(..) = route {
+                if !enter(route, cors) {
+                    return None;
+                };
+            };
+
+            let prefix: String = format!("{prefix}{pathname}/");
+            let mut found: RouteMatch = find_in(next_routes, request, &prefix, cors, enter)?;
+
+            /*- The innermost LimitStack decides the maximum body size -*/
+            if let Route::LimitStack(max_body, _, _) = route {
+                found.max_body.get_or_insert(*max_body);
+            };
+            Some(found)
+        }
+        Route::Post(pathname, _)
+        | Route::Get(pathname, _)
+        | Route::Any(pathname, _)
+        | Route::Method(_, pathname, _) => {
+            /*- Check if it's the requested path, and get the url parameters -*/
+            let template: String = format!("{prefix}{pathname}");
+            let params: HashMap<String, String> = match_path(&template, request.path)?;
+
+            /*- Preflight requests are answered for all endpoints,
+            unless the endpoint handles OPTIONS requests itself -*/
+            let preflight: bool = request.method == Method::OPTIONS
+                && !matches!(route, Route::Method(Method::OPTIONS, _, _));
+            if !preflight && !method_allowed(route, request.method) {
+                return None;
+            };
+
+            Some(RouteMatch {
+                route,
+                template,
+                params,
+                preflight,
+                cors,
+                max_body: None,
+                negotiated: false,
+            })
+        }
+        Route::File(endpoint_path, _) => {
+            let template: String = format!("{prefix}{endpoint_path}");
+            if trim(template.clone()) != trim(request.path.to_string()) {
+                return None;
+            };
+
+            /*- Files are only served for GET and HEAD requests, apart from preflights -*/
+            let preflight: bool = request.method == Method::OPTIONS;
+            if !preflight && !method_allowed(route, request.method) {
+                return None;
+            };
+
+            Some(RouteMatch {
+                route,
+                template,
+                params: HashMap::new(),
+                preflight,
+                cors,
+                max_body: None,
+                negotiated: false,
+            })
+        }
+
+        /*- Fallbacks are only tried by find_in, once no other route matched -*/
+        Route::Fallback(_) => None,
+    }
+}
+
+/*- Find the route in a stack which handles a request. If none does, the
+fallback of the stack handles requests to paths inside of it, (unless a
+route matches the path, but not the method or Accept header) -*/
+fn find_in<'r>(
+    routes: &'r [Route],
+    request: &RouteRequest,
+    prefix: &str,
+    cors: Option<Option<Cors>>,
+    enter: &mut dyn FnMut(&Route, Option<Option<Cors>>) -> bool,
+) -> Option<RouteMatch<'r>> {
+    if let Some(found) = routes
+        .iter()
+        .find_map(|route| find_route(route, request, prefix, cors, enter))
+    {
+        return Some(found);
+    };
+    let fallback: &Route = routes
+        .iter()
+        .find(|route| matches!(route, Route::Fallback(_)))?;
+
+    /*- Fallbacks outside of stacks would catch every path, and with that
+    replace static files, embedded assets and the server's not found page -*/
+    let depth: usize = get_subpaths(prefix).len();
+    if depth == 0 {
+        return None;
+    };
+
+    /*- The stack prefix can contain url parameters, which are
+    matched against the first segments of the request path -*/
+    let request_subpaths: Vec<&str> = get_subpaths(request.path);
+    if request_subpaths.len() < depth {
+        return None;
+    };
+    let params: HashMap<String, String> = match_path(prefix, &request_subpaths[..depth].join("/"))?;
+
+    let mut allowed: Vec<&'static str> = Vec::new();
+    allowed_methods(routes, prefix, request, true, &mut allowed);
+    if !allowed.is_empty() {
+        return None;
+    };
+
+    Some(RouteMatch {
+        route: fallback,
+        template: format!("{prefix}*"),
+        params,
+        preflight: false,
+        cors,
+        max_body: None,
+        negotiated: false,
+    })
+}
+
+/*- Execute an api function, or serve the file of the matched route -*/
+fn call_endpoint(found: RouteMatch, stream: &mut Stream) {
+    if let Some(cors) = found.cors {
+        stream.set_cors(cors);
+    };
+    stream.set_route_template(found.template);
+
+    if found.preflight {
+        return stream.respond_status(200u16);
+    };
+    match found.route {
+        Route::Post(_, function_ptr)
+        | Route::Get(_, function_ptr)
+        | Route::Any(_, function_ptr)
+        | Route::Method(_, _, function_ptr)
+        | Route::Fallback(function_ptr) => {
+            /*- Call the associated function -*/
+            stream.set_params(found.params);
+            function_ptr(stream);
+        }
+        Route::File(_, file_path) => stream.respond_file(200u16, file_path),
+        _ => (),
+    };
+}
+
+/*- Check if a route accepts a http-method -*/
+fn method_allowed(route: &Route, method: Method) -> bool {
+    match route {
+        Route::Get(..) => matches!(method, Method::GET | Method::HEAD),
+        Route::Post(..) => method == Method::POST,
+        Route::Method(route_method, ..) => method == *route_method,
+        Route::File(..) => matches!(method, Method::GET | Method::HEAD),
+        _ => method != Method::UNKNOWN,
+    }
+}
+
+/*- Collect the http-methods of all routes which match the request path, (or all
+routes, for OPTIONS *). Origin control functions and endpoints are never called here.
+Accept stacks are skipped if the request doesn't accept them, unless `any_accept` -*/
+fn allowed_methods(
+    routes: &[Route],
+    prefix: &str,
+    request: &RouteRequest,
+    any_accept: bool,
+    allowed: &mut Vec<&'static str>,
+) {
+    for route in routes {
+        let (pathname, methods): (&str, &[Method]) = match route {
+            Route::Host(hostname, next_routes) => {
+                if host_matches(hostname, request.host) {
+                    allowed_methods(next_routes, prefix, request, any_accept, allowed);
+                };
+                continue;
+            }
+            Route::Accept(_, next_routes) => {
+                if accept_matches(route, request, any_accept) {
+                    allowed_methods(next_routes, prefix, request, any_accept, allowed);
+                };
+                continue;
+            }
+            Route::Stack(pathname, next_routes)
+            | Route::CorsStack(_, pathname, next_routes)
+            | Route::LimitStack(_, pathname, next_routes)
+            | Route::ControlledStack(_, pathname, next_routes) => {
+                let prefix: String = format!("{prefix}{pathname}/");
+                allowed_methods(next_routes, &prefix, request, any_accept, allowed);
+                continue;
+            }
+            _ => match endpoint_methods(route) {
+                Some(e) => e,
+                None => continue,
+            },
+        };
+
+        /*- Route files don't have url parameters, and the
+        asterisk-form (OPTIONS *) matches all routes -*/
+        let full_path: String = format!("{prefix}{pathname}");
+        let matches: bool = match route {
+            _ if request.path == "*" => true,
+            Route::File(..) => trim(full_path) == trim(request.path.to_string()),
+            _ => match_path(&full_path, request.path).is_some(),
+        };
+        if matches {
+            for method in methods {
+                if !allowed.contains(&method.as_str()) {
+                    allowed.push(method.as_str());
+                };
+            }
+        };
+    }
+}
+
+/*- The path and http-methods of an endpoint or file route -*/
+fn endpoint_methods(route: &Route) -> Option<(&'static str, &[Method])> {
+    match route {
+        Route::Get(pathname, _) => Some((pathname, &[Method::GET, Method::HEAD])),
+        Route::Post(pathname, _) => Some((pathname, &[Method::POST])),
+        Route::Method(method, pathname, _) => Some((pathname, std::slice::from_ref(method))),
+        Route::File(pathname, _) => Some((pathname, &[Method::GET, Method::HEAD])),
+        Route::Any(pathname, _) => Some((
+            pathname,
+            &[
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::DELETE,
+                Method::PATCH,
+                Method::HEAD,
+            ],
+        )),
+        _ => None,
+    }
+}
+
+/*- Collect the http-methods and full paths of all endpoint and file routes -*/
+fn list_routes(routes: &[Route], prefix: &str, list: &mut Vec<(Method, String)>) {
+    for route in routes {
+        match route {
+            Route::Host(_, next_routes) | Route::Accept(_, next_routes) => {
+                list_routes(next_routes, prefix, list)
+            }
+            Route::Stack(pathname, next_routes)
+            | Route::CorsStack(_, pathname, next_routes)
+            | Route::LimitStack(_, pathname, next_routes)
+            | Route::ControlledStack(_, pathname, next_routes) => {
+                list_routes(next_routes, &format!("{prefix}{pathname}/"), list)
+            }
+            _ => {
+                if let Some((pathname, methods)) = endpoint_methods(route) {
+                    for method in methods {
+                        list.push((*method, format!("{prefix}{pathname}")));
+                    }
+                };
+            }
+        };
+    }
+}
+
+/*- Check if the request accepts the media type of a Route::Accept. Requests
+without an Accept header accept anything, and so does the asterisk-form -*/
+fn accept_matches(route: &Route, request: &RouteRequest, any_accept: bool) -> bool {
+    match route {
+        Route::Accept(media_type, _) => {
+            any_accept
+                || request.path == "*"
+                || request
+                    .accept
+                    .is_none_or(|accept| utils::headers::accepts(accept, media_type))
+        }
+        _ => true,
+    }
+}
+
+/*- Check if the requested host is the host of a Route::Host -*/
+fn host_matches(hostname: &str, host: Option<&str>) -> bool {
+    match hostname.starts_with("*.") {
+        true => host
+            .and_then(|host| wildcard_subdomain(hostname, host))
+            .is_some(),
+        false => host.is_some_and(|host| host.eq_ignore_ascii_case(hostname)),
+    }
+}
+
+/*- Get the subdomain which a wildcard host, (ex "*.example.com") matches.
+Only single labels match, so "a.b.example.com" and "example.com" don't -*/
+fn wildcard_subdomain<'h>(hostname: &str, host: &'h str) -> Option<&'h str> {
+    let domain: &str = hostname.strip_prefix("*.")?;
+    let (label, rest) = host.split_once('.')?;
+    match !label.is_empty() && rest.eq_ignore_ascii_case(domain) {
+        true => Some(label),
+        false => None,
+    }
+}
+
+/*- Check if a request path matches a route path, and collect the url
+parameters. An url parameter is a "variable" which will be set in the
+url. Example: localhost:8000/day/:day: -*/
+fn match_path(route_path: &str, request_path: &str) -> Option<HashMap<String, String>> {
+    let mut route_subpaths: Vec<&str> = get_subpaths(route_path);
+    let request_subpaths: Vec<&str> = get_subpaths(request_path);
+
+    /*- An optional last parameter, (ex ":page?:") can be left out -*/
+    let optional_last: bool = route_subpaths
+        .last()
+        .and_then(|subpath| is_url_param(subpath))
+        .is_some_and(|(param_name, _)| param_name.ends_with('?'));
+    if optional_last && route_subpaths.len() == request_subpaths.len() + 1 {
+        route_subpaths.pop();
+    } else if route_subpaths.len() != request_subpaths.len() {
+        return None;
+    };
+
+    /*- Iterate and find url params -*/
+    let mut params: HashMap<String, String> = HashMap::new();
+    for (route_subpath, request_subpath) in route_subpaths.iter().zip(request_subpaths.iter()) {
+        match is_url_param(route_subpath) {
+            Some((param_name, constraint)) => {
+                if !constraint.is_none_or(|constraint| param_matches(constraint, request_subpath)) {
+                    return None;
+                };
+                params.insert(
+                    param_name.trim_end_matches('?').into(),
+                    request_subpath.to_string(),
+                );
+            }
+            None => {
+                if route_subpath != request_subpath {
+                    return None;
+                };
+            }
+        }
+    }
+
+    Some(params)
+}
+
+/*- Trim paths with trailing and leading slashes -*/
+pub fn trim(input: String) -> String {
+    let mut output = input.clone();
+    if output.ends_with('/') {
+        output.pop();
+    };
+    if output.starts_with('/') {
+        output.remove(0);
+    };
+    output
+}
+
+/*- Get subpaths of a full path. Example: get_subpaths("/Path/to/value") -> vec!["Path", "to", "value"] -*/
+fn get_subpaths(path: &str) -> Vec<&str> {
+    let mut subpaths: Vec<&str> = Vec::new();
+
+    /*- Iterate over all subpaths -*/
+    for subpath in path.split('/') {
+        if !subpath.is_empty() {
+            subpaths.push(subpath);
+        };
+    }
+
+    /*- Return -*/
+    subpaths
+}
+
+/*- Check if a path is a url parameter, and get its name and type
+constraint. Example: ":id:" -> ("id", None), ":id:i64:" -> ("id", Some("i64")) -*/
+fn is_url_param(path: &str) -> Option<(&str, Option<&str>)> {
+    if path.len() > 1 && path.starts_with(':') && path.ends_with(':') {
+        match path[1..path.len() - 1].split_once(':') {
+            Some((name, constraint)) => Some((name, Some(constraint))),
+            None => Some((&path[1..path.len() - 1], None)),
+        }
+    } else {
+        None
+    }
+}
+
+/*- Check if an url parameter value satisfies a type constraint.
+Unknown constraints never match -*/
+fn param_matches(constraint: &str, value: &str) -> bool {
+    match constraint {
+        "i64" => value.parse::<i64>().is_ok(),
+        "u64" => value.parse::<u64>().is_ok(),
+        "f64" => value.parse::<f64>().is_ok(),
+        "uuid" => {
+            let groups: Vec<&str> = value.split('-').collect();
+            groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+                && groups
+                    .iter()
+                    .all(|group| group.chars().all(|c| c.is_ascii_hexdigit()))
+        }
+        _ => false,
+    }
+}
+
+/*- Respond with the favicon, which browsers may cache for a week -*/
+fn serve_favicon(favicon: Favicon, stream: &mut Stream) {
+    let (path, icon): (&str, Cow<[u8]>) = match favicon {
+        Favicon::None => return stream.respond_status(204u16),
+        Favicon::Bytes(bytes) => ("favicon.ico", Cow::Borrowed(bytes)),
+        Favicon::File(path) => match cached_file(Path::new(path)) {
+            Some(buf) => (path, Cow::Owned(buf)),
+            None => match fs::read(path) {
+                Ok(buf) => (path, Cow::Owned(buf)),
+                Err(_) => return stream.respond_status(404u16),
+            },
+        },
+    };
+
+    let mut icon = std::io::Cursor::new(icon);
+    let response_type: ResponseType = stream.file_type(Path::new(path), &mut icon);
+    let length: u64 = icon.get_ref().len() as u64;
+    stream.respond_reader(
+        200u16,
+        response_type,
+        length,
+        icon,
+        Some(vec![String::from("Cache-Control: public, max-age=604800")]),
+    );
+}
+
+/*- Serve static files from a specified dir. With clean urls, extensionless
+paths which aren't found, (ex /about) are served from html files, (about.html) -*/
+fn serve_static_dir(
+    dir: &str,
+    request_path: &str,
+    miss_cache: Option<(Duration, usize)>,
+    clean_urls: bool,
+    serve_dotfiles: bool,
+    stream: &mut Stream,
+) -> Result<(), ()> {
+    /*- Paths with hidden segments, (ex /.env or /.git/config) are refused before
+    touching the disk. Parent segments (..) would leave the dir, so they always are -*/
+    if request_path.split(['/', '\\']).any(|segment| {
+        matches!(segment, "." | "..") || (!serve_dotfiles && segment.starts_with('.'))
+    }) {
+        return Err(());
+    };
+
+    match serve_static_file(dir, request_path, miss_cache, stream) {
+        Err(_)
+            if clean_urls
+                && !request_path.ends_with('/')
+                && Path::new(request_path).extension().is_none()
+                && !Path::new(&[dir, request_path].concat()).is_dir() =>
+        {
+            serve_static_file(dir, &format!("{request_path}.html"), miss_cache, stream)
+        }
+        result => result,
+    }
+}
+
+/*- Serve a single file from a static dir -*/
+fn serve_static_file(
+    dir: &str,
+    request_path: &str,
+    miss_cache: Option<(Duration, usize)>,
+    stream: &mut Stream,
+) -> Result<(), ()> {
     /*- Get the requested file path -*/
     let path = &[dir, request_path].concat();
     let file_path: &Path = Path::new(path);
 
-    /*- Find if exists in file cache -*/
-    if let Ok(fc) = FILE_CACHE.lock() {
-        match fc.get(path) {
-            Some(buf) => {
-                stream.respond(
-                    200,
-                    Respond::new().content(
-                        &String::from_utf8_lossy(&buf),
-                        ResponseType::guess(file_path),
-                    ),
-                );
-                return Ok(());
-            }
-            None => (),
-        }
-    };
+    /*- Paths which were missing recently are skipped without touching the disk -*/
+    if let Some((ttl, _)) = miss_cache {
+        if MISS_CACHE.lock().is_ok_and(|misses| {
+            misses
+                .get(path)
+                .is_some_and(|missed| missed.elapsed() < ttl)
+        }) {
+            return Err(());
+        };
+    };
+
+    /*- Find if exists in file cache -*/
+    if stream.respond_cached(200u16, file_path) {
+        return Ok(());
+    };
+
+    /*- Check path availability -*/
+    match file_path.is_file() {
+        true => (),
+        false => {
+            if let Some((ttl, max_entries)) = miss_cache {
+                cache_miss(path, ttl, max_entries);
+            };
+            return Err(());
+        }
+    };
+
+    /*- Open file, and stream it from disk instead of reading it into memory -*/
+    let mut file: fs::File = match fs::File::open(file_path) {
+        Ok(e) => e,
+        Err(_) => return Err(()),
+    };
+    let metadata: fs::Metadata = match file.metadata() {
+        Ok(e) => e,
+        Err(_) => return Err(()),
+    };
+
+    /*- Respond -*/
+    let response_type: ResponseType = stream.file_type(file_path, &mut file);
+    stream.respond_file_reader(
+        200u16,
+        response_type,
+        metadata.len(),
+        metadata.modified().ok(),
+        file,
+    );
+
+    Ok(())
+}
+
+/*- Remember that a static file path is missing. Expired entries are removed
+when the cache is full, and if it's still full the miss isn't remembered -*/
+fn cache_miss(path: &str, ttl: Duration, max_entries: usize) {
+    let mut misses = match MISS_CACHE.lock() {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    if misses.len() >= max_entries {
+        misses.retain(|_, missed| missed.elapsed() < ttl);
+    };
+    if misses.len() < max_entries {
+        misses.insert(path.to_string(), Instant::now());
+    };
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/*- Builder pattern for server config struct -*/
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            addr: None,
+            port: None,
+            num_threads: 1,
+            acceptor_threads: 1,
+            thread_name_prefix: String::from("responder-worker"),
+            serve: None,
+            not_found: None,
+            routes: Cow::Borrowed(&[]),
+            init_buf: None,
+            max_body_size: None,
+            max_headers: None,
+            max_uri_length: None,
+            max_path_segments: PATH_SEGMENTS_MAX,
+            embedded: HashMap::new(),
+            write_timeout: None,
+            request_timeout: None,
+            keep_alive: None,
+            cache: None,
+            cache_exclude: Vec::new(),
+            cache_compressed: false,
+            miss_cache: None,
+            default_content_type: None,
+            error_format: ErrorFormat::Text,
+            error_page_template: None,
+            missing_keys: MissingKeys::Empty,
+            per_ip_concurrency: None,
+            active_requests: Arc::new(Mutex::new(HashMap::new())),
+            connection_reaper: None,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            logs: true,
+            cors: false,
+            clean_urls: false,
+            serve_dotfiles: false,
+            favicon: None,
+            trace: false,
+            method_override: false,
+            redirect_body: None,
+            default_headers: Vec::new(),
+            #[cfg(feature = "serde")]
+            audit_log: None,
+            canonicalize_headers: false,
+            shutdown_timeout: Duration::from_secs(30),
+            drain_timeout: None,
+            body_mode: BodyMode::Buffered,
+            on_request_start: None,
+            on_request_end: None,
+            on_upgrade: None,
+        }
+    }
+    /// `[REQUIRED]` The server port
+    pub fn port(&mut self, port: u16) -> &mut Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Serve static files from a directory
+    pub fn serve(&mut self, serve: &'static str) -> &mut Self {
+        self.serve = Some(serve);
+        self
+    }
+
+    /// Serve files which are compiled into the binary, (using `include_bytes!`)
+    /// so no static directory needs to be shipped with the executable. Paths are
+    /// relative to the server root, and embedded files are checked before the
+    /// directory set with `serve`.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().serve_embedded(&[
+    ///     ("index.html", b"<h1>Hello</h1>"),
+    ///     ("css/style.css", b"h1 { color: red; }"),
+    ///     /* ("logo.png", include_bytes!("../static/logo.png")) */
+    /// ]);
+    /// ```
+    pub fn serve_embedded(&mut self, files: &[(&str, &'static [u8])]) -> &mut Self {
+        for (path, bytes) in files {
+            self.embedded.insert(trim(path.to_string()), bytes);
+        }
+        self
+    }
+
+    /// All http-routes coupled to this server. Takes either a static
+    /// slice of routes, or a `Vec<Route>` which has been built at runtime.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// let mut routes = vec![Route::Get("", |stream| stream.respond_status(200u16))];
+    /// if std::env::var("ENABLE_ADMIN").is_ok() {
+    ///     routes.push(Route::Get("admin", |stream| stream.respond_status(200u16)));
+    /// };
+    ///
+    /// Server::new().routes(routes);
+    /// ```
+    pub fn routes(&mut self, routes: impl Into<Cow<'static, [Route]>>) -> &mut Self {
+        self.routes = routes.into();
+        self
+    }
+
+    /// List the http-methods and full paths of all endpoint and file
+    /// routes, (in the order they're tried) with the paths of the stacks
+    /// they're in. Useful for printing the available endpoints at startup,
+    /// or generating api documentation. Fallbacks aren't listed.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// let mut server = Server::new();
+    /// server.routes(&[
+    ///     Route::Stack("users", &[
+    ///         Route::Post(":id:u64:", |stream| {}),
+    ///     ]),
+    /// ][..]);
+    ///
+    /// for (method, path) in server.route_list() {
+    ///     println!("{} /{path}", method.as_str());
+    /// }
+    /// assert_eq!(server.route_list(), vec![(Method::POST, String::from("users/:id:u64:"))]);
+    /// ```
+    pub fn route_list(&self) -> Vec<(Method, String)> {
+        let mut list: Vec<(Method, String)> = Vec::new();
+        list_routes(&self.routes, "", &mut list);
+        list
+    }
+
+    /// `[REQUIRED]` The server address
+    pub fn address(&mut self, addr: &'static str) -> &mut Self {
+        self.addr = Some(addr);
+        self
+    }
+
+    /// The number of threads the current server will use as a maximum
+    pub fn threads(&mut self, num_threads: u16) -> &mut Self {
+        self.num_threads = num_threads;
+        self
+    }
+
+    /// The number of threads accepting new connections, which are then
+    /// handled by the worker threads. Accepting is fast, so one is enough
+    /// for most servers, but very high connection rates benefit from more.
+    /// Defaults to 1.
+    pub fn acceptor_threads(&mut self, acceptor_threads: u16) -> &mut Self {
+        self.acceptor_threads = acceptor_threads.max(1);
+        self
+    }
+
+    /// Worker threads are named `{prefix}-{index}`, which shows up in
+    /// panic messages, profilers and debuggers. Defaults to `responder-worker`
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new()
+    ///     .port(8080)
+    ///     .threads(8)
+    ///     .thread_name_prefix("api-worker");
+    /// ```
+    pub fn thread_name_prefix(&mut self, prefix: &str) -> &mut Self {
+        self.thread_name_prefix = prefix.to_string();
+        self
+    }
+
+    /// Path to a 404 page, if not specified server will return "404 Not Found".
+    /// The page is read into memory when the server starts, so 404s (like
+    /// from scanners) don't read it from the disk every time.
+    pub fn not_found(&mut self, not_found: &'static str) -> &mut Self {
+        self.not_found = Some(not_found);
+        self
+    }
+
+    /// How request bodies are recieved. Bodies are buffered by default, but
+    /// with `BodyMode::Streaming` they are left for handlers to read, (using
+    /// `stream.body_reader()` or `stream.save_body_to()`) so big uploads
+    /// don't have to fit into memory. Streamed bodies aren't decoded, even
+    /// with the `gzip` feature.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use responder::request::BodyMode;
+    ///
+    /// fn upload(stream: &mut Stream) {
+    ///     match stream.save_body_to("/tmp/upload.bin") {
+    ///         Ok(_) => stream.respond_status(201u16),
+    ///         Err(_) => stream.respond_status(400u16),
+    ///     };
+    /// }
+    ///
+    /// Server::new()
+    ///     .port(8080)
+    ///     .routes(&[Route::Post("upload", upload)])
+    ///     .body_mode(BodyMode::Streaming);
+    /// ```
+    pub fn body_mode(&mut self, body_mode: BodyMode) -> &mut Self {
+        self.body_mode = body_mode;
+        self
+    }
+
+    /// The write buffer size when recieving requests in bytes. This is also the maximum
+    /// size of the request line & headers, requests with bigger headers will be
+    /// responded to with `431 Request Header Fields Too Large`
+    pub fn init_buf_size(&mut self, buf_size: usize) -> &mut Self {
+        self.init_buf = Some(buf_size);
+        self
+    }
+
+    /// The maximum size of request bodies in bytes. Requests with
+    /// bigger bodies will be responded to with `413 Payload Too Large`.
+    /// Chunked bodies which are read before routing are limited to 16 MiB
+    /// if this isn't set. With the `gzip` feature, this also limits the size
+    /// of compressed bodies once they've been decoded, (16 MiB if not set).
+    /// Routes can have their own limit using `Route::LimitStack`
+    pub fn max_body_size(&mut self, max_body_size: usize) -> &mut Self {
+        self.max_body_size = Some(max_body_size);
+        self
+    }
+
+    /// The maximum number of headers a request can have. Requests with
+    /// more headers will be responded to with `431 Request Header Fields
+    /// Too Large`. This limits the number of headers, while `init_buf_size`
+    /// limits their total size.
+    pub fn max_headers(&mut self, max_headers: usize) -> &mut Self {
+        self.max_headers = Some(max_headers);
+        self
+    }
+
+    /// The maximum length of the path in the request line, (including the
+    /// query string). Requests with longer paths will be responded to
+    /// with `414 URI Too Long`, before they're routed.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().max_uri_length(2048);
+    /// ```
+    pub fn max_uri_length(&mut self, max_uri_length: usize) -> &mut Self {
+        self.max_uri_length = Some(max_uri_length);
+        self
+    }
+
+    /// The maximum number of segments in the requested path, (`/a/b/c` has
+    /// three). Deeper paths are responded to with the 404 page before they're
+    /// routed, which bounds the work of matching adversarial paths. 64 by default.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().max_path_segments(16);
+    /// ```
+    pub fn max_path_segments(&mut self, max_path_segments: usize) -> &mut Self {
+        self.max_path_segments = max_path_segments;
+        self
+    }
+
+    /// How long writing a response may block, (like when a client stops
+    /// reading) before giving up. The connection is then dropped, so a slow
+    /// client can't occupy a worker thread forever. No timeout by default.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// Server::new().write_timeout(Duration::from_secs(10));
+    /// ```
+    pub fn write_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Give each request a deadline, `timeout` after the server started
+    /// processing it. Handlers read it using `stream.deadline()`, and can
+    /// bail out early with a useful response. The deadline is cooperative,
+    /// handlers which don't check it aren't interrupted.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// Server::new().request_timeout(Duration::from_secs(5));
+    /// ```
+    pub fn request_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep HTTP/1.1 connections open after responding, so clients can send
+    /// more requests (also pipelined ones) on the same connection. Connections
+    /// which are idle for longer than `idle_timeout` are closed. Disabled by
+    /// default, meaning that connections are closed after each response.
+    /// HTTP/1.0 connections are only kept open if the client sends
+    /// `Connection: keep-alive`. Responses tell the client if the connection
+    /// stays open using the `Connection` header.
+    ///
+    /// Every open connection occupies a worker thread, so the number of
+    /// threads is also the number of clients which can be connected at once.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// Server::new().keep_alive(Duration::from_secs(5));
+    /// ```
+    pub fn keep_alive(&mut self, idle_timeout: Duration) -> &mut Self {
+        self.keep_alive = Some(idle_timeout);
+        self
+    }
+
+    /// Serve extensionless paths which aren't found in the static dir
+    /// from html files, so `/about` serves `about.html`. Directories
+    /// and paths ending with a slash are left as they are.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new()
+    ///     .serve("./static")
+    ///     .clean_urls();
+    /// ```
+    pub fn clean_urls(&mut self) -> &mut Self {
+        self.clean_urls = true;
+        self
+    }
+
+    /// Serve files and directories starting with a `.` from the static
+    /// dir, (ex `/.well-known/security.txt`). They're responded to with
+    /// `404 Not Found` by default, so files like `.env` or `.git/config`
+    /// which ended up in the dir aren't leaked. Paths containing `..` are
+    /// never served.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new()
+    ///     .serve("./static")
+    ///     .serve_dotfiles();
+    /// ```
+    pub fn serve_dotfiles(&mut self) -> &mut Self {
+        self.serve_dotfiles = true;
+        self
+    }
+
+    /// Respond to requests for `/favicon.ico`, (which browsers send by
+    /// themselves) with an icon file. Routes for the path take precedence.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().favicon("./static/favicon.ico");
+    /// ```
+    pub fn favicon(&mut self, path: &'static str) -> &mut Self {
+        self.favicon = Some(Favicon::File(path));
+        self
+    }
+
+    /// Respond to requests for `/favicon.ico` with an icon
+    /// which is embedded into the binary, (see `favicon`)
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().favicon_bytes(b"\x89PNG\r\n\x1a\n");
+    /// /* .favicon_bytes(include_bytes!("../static/favicon.ico")) */
+    /// ```
+    pub fn favicon_bytes(&mut self, icon: &'static [u8]) -> &mut Self {
+        self.favicon = Some(Favicon::Bytes(icon));
+        self
+    }
+
+    /// Respond to requests for `/favicon.ico` with `204 No Content`, so
+    /// sites without an icon don't send their 404 page for every visit
+    pub fn favicon_none(&mut self) -> &mut Self {
+        self.favicon = Some(Favicon::None);
+        self
+    }
+
+    /// If file caching should be enabled or not (for the directory specified in the serve function)
+    pub fn cache_serve_dir(&mut self) -> &mut Self {
+        self.cache = Some(FileCacheType::All);
+        self
+    }
+
+    /// Store cached files gzip compressed, which uses less memory for text
+    /// files. They're sent compressed to clients which accept gzip, and are
+    /// decompressed for the ones which don't. Files which don't get smaller
+    /// when compressed, (like images) are stored as they are.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new()
+    ///     .serve("./static")
+    ///     .cache_serve_dir()
+    ///     .cache_compressed();
+    /// ```
+    #[cfg(feature = "gzip")]
+    pub fn cache_compressed(&mut self) -> &mut Self {
+        self.cache_compressed = true;
+        self
+    }
+
+    /// If file caching should be enabled or not (for specified file paths)
+    pub fn cache_selected(&mut self, selection: &'static [&'static str]) -> &mut Self {
+        self.cache = Some(FileCacheType::Selection(selection));
+        self
+    }
+
+    /// Look for idle connections every `interval`, and close the ones which
+    /// have waited for a request for longer than the keep-alive idle timeout,
+    /// (30 seconds if keep-alive is disabled). Unlike socket timeouts, this
+    /// also closes connections which send their headers very slowly, (like
+    /// slow-loris attacks) so they can't keep worker threads occupied.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// Server::new()
+    ///     .keep_alive(Duration::from_secs(5))
+    ///     .connection_reaper(Duration::from_secs(1));
+    /// ```
+    pub fn connection_reaper(&mut self, interval: Duration) -> &mut Self {
+        self.connection_reaper = Some(interval);
+        self
+    }
+
+    /// The number of requests a single client, (by ip address) can have
+    /// being handled at once. Requests over the limit are responded to
+    /// with `429 Too Many Requests`, so one client can't occupy all of the
+    /// worker threads. Idle keep-alive connections don't count.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new()
+    ///     .threads(16)
+    ///     .per_ip_concurrency(4);
+    /// ```
+    pub fn per_ip_concurrency(&mut self, max: usize) -> &mut Self {
+        self.per_ip_concurrency = Some(max);
+        self
+    }
+
+    /// The content type of files whose type can't be guessed from their
+    /// extension, or from their first bytes, (see `ResponseType::guess_bytes`).
+    /// Otherwise files without an extension are sent as
+    /// `text/plain`, and files with unknown extensions as binary data.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use responder::response::ResponseType;
+    ///
+    /// Server::new()
+    ///     .serve("./static")
+    ///     .default_content_type(ResponseType::OctetStream);
+    /// ```
+    pub fn default_content_type(&mut self, response_type: ResponseType) -> &mut Self {
+        self.default_content_type = Some(response_type);
+        self
+    }
+
+    /// The body format of error responses, (like 404 and 405) which the
+    /// server sends itself, or which are sent using `stream.respond_status`.
+    /// Plain text by default. Custom 404 pages are still sent as they are.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use responder::response::ErrorFormat;
+    ///
+    /// /* 404 responses will look like this: {"status":404,"error":"Not Found"} */
+    /// Server::new().error_format(ErrorFormat::Json);
+    /// ```
+    pub fn error_format(&mut self, format: ErrorFormat) -> &mut Self {
+        self.error_format = format;
+        self
+    }
+
+    /// Render error responses, (4xx and 5xx without a body) with an html
+    /// template instead of plain text, so one page can be used for all
+    /// statuses. The `{{status}}`, `{{reason}}` and `{{path}}` placeholders
+    /// are replaced with the details of the response, html escaped. Not used
+    /// with `ErrorFormat::Json`, or for the `not_found` page. The template is
+    /// read into memory when the server starts, and if it can't be read, plain
+    /// text is sent instead.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /* <h1>{{status}} {{reason}}</h1><p>Nothing to see at {{path}}</p> */
+    /// Server::new().error_page_template("./static/error.html");
+    /// ```
+    pub fn error_page_template(&mut self, path: &'static str) -> &mut Self {
+        self.error_page_template = Some(path);
+        self
+    }
 
-    /*- Check path availability -*/
-    match file_path.is_file() {
-        true => (),
-        false => return Err(()),
-    };
-
-    /*- Open file -*/
-    match fs::File::open(file_path) {
-        Ok(_) => {
-            /*- Get file content -*/
-            let mut file_content: String = match fs::read_to_string(file_path) {
-                Ok(e) => e,
-                Err(_) => {
-                    /*- If we can't read the file, we'll send a 404 page -*/
-                    return Err(());
-                }
-            };
-            let res: Respond = Respond {
-                response_type: ResponseType::guess(file_path),
-                content: Some(file_content),
-                additional_headers: None,
-            };
+    /// What `stream.respond_template` does with placeholders which aren't
+    /// in the context. They render empty by default, and `MissingKeys::Error`
+    /// responds with `500 Internal Server Error` instead, (which makes typos
+    /// in templates easier to notice)
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use responder::response::MissingKeys;
+    ///
+    /// Server::new().missing_template_keys(MissingKeys::Error);
+    /// ```
+    pub fn missing_template_keys(&mut self, missing_keys: MissingKeys) -> &mut Self {
+        self.missing_keys = missing_keys;
+        self
+    }
 
-            /*- Respond -*/
-            stream.respond(200u16, res);
-        }
-        Err(_) => return Err(()),
+    /// Remember static file paths which don't exist for `ttl`, so requests
+    /// to them (like from scanners probing for files) are answered with 404
+    /// without touching the disk. At most `max_entries` paths are remembered.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// Server::new()
+    ///     .serve("./static")
+    ///     .cache_misses(Duration::from_secs(10), 10_000);
+    /// ```
+    pub fn cache_misses(&mut self, ttl: Duration, max_entries: usize) -> &mut Self {
+        self.miss_cache = Some((ttl, max_entries));
+        self
     }
 
-    Ok(())
-}
+    /// Files which shouldn't be cached, (like often changing files) and
+    /// are read from disk on every request instead. Patterns like `*.xml`
+    /// match extensions, and other patterns match the end of file paths.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new()
+    ///     .serve("./static")
+    ///     .cache_serve_dir()
+    ///     .cache_exclude(&["sitemap.xml", "*.json"]);
+    /// ```
+    pub fn cache_exclude(&mut self, patterns: &[&str]) -> &mut Self {
+        self.cache_exclude
+            .extend(patterns.iter().map(|pattern| pattern.to_string()));
+        self
+    }
 
-/*- Builder pattern for server config struct -*/
-impl<'f> Server {
-    pub fn new() -> Server {
-        Server {
-            addr: None,
-            port: None,
-            num_threads: 1,
-            serve: None,
-            not_found: None,
-            routes: &[],
-            init_buf: None,
-            cache: None,
-            logs: true,
-            cors: false,
-        }
+    /// Disable server logging to `stdout` (Server outputs when caching files / on server start)
+    pub fn no_logs(&mut self) -> &mut Self {
+        self.logs = false;
+        self
     }
-    /// `[REQUIRED]` The server port
-    pub fn port(&mut self, port: u16) -> &mut Self {
-        self.port = Some(port);
+
+    /// If CORS should be enabled or not
+    pub fn cors(&mut self) -> &mut Self {
+        self.cors = true;
         self
     }
 
-    /// Serve static files from a directory
-    pub fn serve(&mut self, serve: &'static str) -> &mut Self {
-        self.serve = Some(serve);
+    /// Respond to TRACE requests by echoing the request back. Disabled by
+    /// default (responding with `405`), because echoing requests will also
+    /// echo sensitive headers like cookies back to the client.
+    pub fn trace(&mut self) -> &mut Self {
+        self.trace = true;
         self
     }
 
-    /// All http-routes coupled to this server
-    pub fn routes(&mut self, routes: &'static [Route]) -> &mut Self {
-        self.routes = routes;
+    /// Let POST requests be routed as PUT, DELETE or PATCH requests, using
+    /// the `X-HTTP-Method-Override` header or a `_method` form field. Useful
+    /// for html forms, which can only send GET and POST requests.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /* <form method="post"><input type="hidden" name="_method" value="DELETE"></form> */
+    /// Server::new()
+    ///     .port(8080)
+    ///     .routes(&[Route::Method(Method::DELETE, "post", |stream| {})])
+    ///     .method_override();
+    /// ```
+    pub fn method_override(&mut self) -> &mut Self {
+        self.method_override = true;
         self
     }
 
-    /// `[REQUIRED]` The server address
-    pub fn address(&mut self, addr: &'static str) -> &mut Self {
-        self.addr = Some(addr);
+    /// Customize the html body which is sent with `stream.redirect(url)`,
+    /// for clients that don't follow the redirect. Takes the url as input.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().redirect_body_template(|url| {
+    ///     format!("<a href=\"{url}\">Klicka här om du inte skickas vidare</a>")
+    /// });
+    /// ```
+    pub fn redirect_body_template(&mut self, template: fn(&str) -> String) -> &mut Self {
+        self.redirect_body = Some(template);
         self
     }
 
-    /// The number of threads the current server will use as a maximum
-    pub fn threads(&mut self, num_threads: u16) -> &mut Self {
-        self.num_threads = num_threads;
+    /// Headers which are sent with every response, like security headers.
+    /// Setting a header which already is a default header replaces it. If
+    /// an endpoint responds with a header of the same name (case-insensitive),
+    /// the endpoint's header is sent instead of the default one.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().default_headers(&[
+    ///     ("X-Content-Type-Options", "nosniff"),
+    ///     ("X-Frame-Options", "DENY"),
+    /// ]);
+    /// ```
+    pub fn default_headers(&mut self, headers: &[(&str, &str)]) -> &mut Self {
+        for (key, value) in headers {
+            self.default_headers
+                .retain(|(existing, _)| !existing.eq_ignore_ascii_case(key));
+            self.default_headers
+                .push((key.to_string(), value.to_string()));
+        }
         self
     }
 
-    /// Path to a 404 page, if not specified server will return "404 Not Found"
-    pub fn not_found(&mut self, not_found: &'static str) -> &mut Self {
-        self.not_found = Some(not_found);
+    /// Log every request and its response as a line of json, with the
+    /// method, path, status, duration, request headers and the bodies of
+    /// both. Entries go to stderr, unless another `sink` is configured.
+    /// Secrets are redacted as configured, (headers by name, and json
+    /// bodies field by field). Other bodies mentioning a redacted field, like
+    /// forms, are left out entirely. Bodies are truncated to `max_body` bytes.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use responder::audit::AuditConfig;
+    ///
+    /// Server::new().audit_log(AuditConfig {
+    ///     redact_headers: &["Authorization", "Cookie", "X-Api-Key"],
+    ///     ..AuditConfig::DEFAULT
+    /// });
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn audit_log(&mut self, config: audit::AuditConfig) -> &mut Self {
+        self.audit_log = Some(config);
         self
     }
 
-    /// The write buffer size when recieving requests in bytes
-    pub fn init_buf_size(&mut self, buf_size: usize) -> &mut Self {
-        self.init_buf = Some(buf_size);
+    /// Write the names of all response headers in Title-Case, (ex
+    /// `content-type` as `Content-Type`) no matter how they were written
+    /// in `Respond::headers` or `default_headers`. Some proxies and clients
+    /// are picky about how header names are cased.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().canonicalize_headers();
+    /// ```
+    pub fn canonicalize_headers(&mut self) -> &mut Self {
+        self.canonicalize_headers = true;
         self
     }
 
-    /// If file caching should be enabled or not (for the directory specified in the serve function)
-    pub fn cache_serve_dir(&mut self) -> &mut Self {
-        self.cache = Some(FileCacheType::All);
+    /// Send a baseline of security headers with every response:
+    /// `X-Content-Type-Options: nosniff`, `X-Frame-Options: DENY` and
+    /// `Referrer-Policy: no-referrer`. Headers which already have been set
+    /// using `default_headers` are kept, and these can be overridden by
+    /// calling `default_headers` afterwards. `Strict-Transport-Security`
+    /// isn't included, because the server doesn't serve https itself.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new()
+    ///     .secure_defaults()
+    ///     .default_headers(&[("X-Frame-Options", "SAMEORIGIN")]);
+    /// ```
+    pub fn secure_defaults(&mut self) -> &mut Self {
+        for (key, value) in [
+            ("X-Content-Type-Options", "nosniff"),
+            ("X-Frame-Options", "DENY"),
+            ("Referrer-Policy", "no-referrer"),
+        ] {
+            if !self
+                .default_headers
+                .iter()
+                .any(|(existing, _)| existing.eq_ignore_ascii_case(key))
+            {
+                self.default_headers
+                    .push((key.to_string(), value.to_string()));
+            };
+        }
         self
     }
 
-    /// If file caching should be enabled or not (for specified file paths)
-    pub fn cache_selected(&mut self, selection: &'static [&'static str]) -> &mut Self {
-        self.cache = Some(FileCacheType::Selection(selection));
+    /// Call a function before each request is routed, once its headers
+    /// and body have been read. Useful for opening tracing spans or
+    /// counting requests.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().on_request_start(|stream| {
+    ///     println!("--> {} {}", stream.method().as_str(), stream.path());
+    /// });
+    /// ```
+    pub fn on_request_start(&mut self, hook: fn(&Stream)) -> &mut Self {
+        self.on_request_start = Some(hook);
         self
     }
 
-    /// Disable server logging to `stdout` (Server outputs when caching files / on server start)
-    pub fn no_logs(&mut self) -> &mut Self {
-        self.logs = false;
+    /// Call a function after each request has been handled, with the
+    /// status of the response (if one was sent) and how long handling
+    /// it took. Useful for recording metrics or closing tracing spans.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().on_request_end(|stream, status, duration| {
+    ///     println!("<-- {} {:?} in {duration:?}", stream.path(), status);
+    /// });
+    /// ```
+    pub fn on_request_end(&mut self, hook: fn(&Stream, Option<u16>, Duration)) -> &mut Self {
+        self.on_request_end = Some(hook);
         self
     }
 
-    /// If CORS should be enabled or not
-    pub fn cors(&mut self) -> &mut Self {
-        self.cors = true;
+    /// Hand HTTP/1.1 requests asking to switch protocols, (with `Connection: Upgrade`
+    /// and an `Upgrade` header, like websocket handshakes) to a function instead of
+    /// the routes. It's called with the protocols from the `Upgrade` header, and
+    /// either responds normally to refuse the upgrade, or responds `101` and takes
+    /// over the connection using `stream.get_mut_inner_ref()`. The connection is
+    /// closed once the function returns, and keeps a worker thread busy until then.
+    /// Without an upgrade handler, the `Upgrade` header is ignored.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new().on_upgrade(|stream, protocol| {
+    ///     if stream.path() != "/ws" || !protocol.eq_ignore_ascii_case("websocket") {
+    ///         return stream.respond_status(404u16);
+    ///     };
+    ///     stream.respond(101u16, Respond::new().headers(vec![
+    ///         String::from("Upgrade: websocket"),
+    ///         String::from("Connection: Upgrade"),
+    ///     ]));
+    ///     let connection = stream.get_mut_inner_ref();
+    ///     /* Speak the new protocol */
+    /// });
+    /// ```
+    pub fn on_upgrade(&mut self, handler: fn(&mut Stream, &str)) -> &mut Self {
+        self.on_upgrade = Some(handler);
         self
     }
 
@@ -544,7 +2660,124 @@ impl<'f> Server {
     ///     // .start()
     ///     // .unwrap();
     /// ```
-    pub fn start(self) -> Result<(), ConfigError> {
+    pub fn start(&self) -> Result<(), ConfigError> {
+        self.bind()?.start();
+
+        /*- Return, even though it will never happen -*/
+        Ok(())
+    }
+
+    /// Bind the server to its address without accepting connections yet.
+    /// The returned `BoundServer` knows the actual address, which is useful
+    /// when binding port 0 to let the OS pick a free port (like in tests).
+    ///
+    /// ## Example:
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// let server = Server::new()
+    ///     .address("127.0.0.1")
+    ///     .port(0)
+    ///     .no_logs()
+    ///     .bind()
+    ///     .unwrap();
+    ///
+    /// let addr = server.local_addr();
+    /// std::thread::spawn(move || server.start());
+    ///
+    /// std::net::TcpStream::connect(addr).unwrap();
+    /// ```
+    pub fn bind(&self) -> Result<BoundServer, ConfigError> {
+        self.serve_on(self.listen()?)
+    }
+
+    /// Use a listener which was already bound, instead of binding the
+    /// address and port of the server. Useful for socket activation,
+    /// (like a listener passed from systemd) and for tests which bind
+    /// the listener themselves. The address and port don't need to be set.
+    ///
+    /// ## Example:
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::net::TcpListener;
+    ///
+    /// let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    /// let server = Server::new()
+    ///     .no_logs()
+    ///     .serve_on(listener)
+    ///     .unwrap();
+    ///
+    /// let addr = server.local_addr();
+    /// std::thread::spawn(move || server.start());
+    ///
+    /// std::net::TcpStream::connect(addr).unwrap();
+    /// ```
+    pub fn serve_on(&self, listener: TcpListener) -> Result<BoundServer, ConfigError> {
+        self.load_cache();
+        let local_addr: SocketAddr = match listener.local_addr() {
+            Ok(e) => e,
+            Err(_) => return Err(ConfigError::HostPortBindingFail),
+        };
+
+        Ok(BoundServer {
+            server: self.clone(),
+            listener,
+            local_addr,
+        })
+    }
+
+    /// Start the server, and stop it when the process recieves Ctrl-C
+    /// (SIGINT) or SIGTERM, like when a container is stopped. New
+    /// connections stop being accepted, and requests which are already
+    /// being handled get `shutdown_timeout` to finish before returning.
+    ///
+    /// Only one signal handler can be installed per process, so this
+    /// can only be called once.
+    ///
+    /// ## Example:
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Server::new()
+    ///     .address("127.0.0.1")
+    ///     .port(8080);
+    /// /* add these */
+    ///     // .start_with_shutdown_signal()
+    ///     // .unwrap();
+    /// ```
+    #[cfg(feature = "signal")]
+    pub fn start_with_shutdown_signal(&self) -> Result<(), ConfigError> {
+        self.bind()?.start_with_shutdown_signal()
+    }
+
+    /// How long requests which are being handled get to finish when the
+    /// server shuts down, before it returns anyways. Defaults to 30 seconds.
+    pub fn shutdown_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// How long connections which have been accepted, but are still waiting
+    /// for a worker thread, keep being handled when the server shuts down.
+    /// Connections still waiting after this are closed without a response.
+    /// By default they're handled until the `shutdown_timeout` runs out.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// Server::new()
+    ///     .shutdown_timeout(Duration::from_secs(30))
+    ///     .drain_timeout(Duration::from_secs(5));
+    /// ```
+    pub fn drain_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.drain_timeout = Some(timeout);
+        self
+    }
+
+    /*- Bind the listener -*/
+    fn listen(&self) -> Result<TcpListener, ConfigError> {
         /*- Get port and address -*/
         let bind_to = &format!(
             "{}:{}",
@@ -558,27 +2791,6 @@ impl<'f> Server {
             }
         );
 
-        /*- If cache is enabled -*/
-        if let Some(cache) = self.cache {
-            match cache {
-                FileCacheType::All => load_files_cache(
-                    self.logs,
-                    get_list_dir(
-                        self.serve
-                            .expect("Calling .cache_serve_dir() requires .serve(dir) to be set"),
-                    ),
-                ),
-                FileCacheType::Selection(selection) => load_files_cache(
-                    self.logs,
-                    selection
-                        .to_owned()
-                        .iter()
-                        .map(|e| e.to_string())
-                        .collect::<Vec<String>>(),
-                ),
-            }
-        };
-
         /*- Start the listener -*/
         let stream = match TcpListener::bind(bind_to) {
             Ok(listener) => listener,
@@ -592,31 +2804,169 @@ impl<'f> Server {
             println!("http://{bind_to}")
         };
 
+        Ok(stream)
+    }
+
+    /*- Load the file cache, if it's enabled. Excluded files are
+    never loaded, so requests for them will be served from disk -*/
+    fn load_cache(&self) {
+        /*- Error pages are always kept in memory, as they're
+        what requests from scanners and other abuse get -*/
+        let error_pages: Vec<String> = [self.not_found, self.error_page_template]
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect();
+        load_files_cache(false, error_pages, self.cache_compressed);
+
+        if let Some(cache) = self.cache {
+            let files: Vec<String> = match cache {
+                FileCacheType::All => get_list_dir(
+                    self.serve
+                        .expect("Calling .cache_serve_dir() requires .serve(dir) to be set"),
+                ),
+                FileCacheType::Selection(selection) => selection
+                    .to_owned()
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<String>>(),
+            };
+            load_files_cache(
+                self.logs,
+                files
+                    .into_iter()
+                    .filter(|file| !cache_excluded(&self.cache_exclude, file))
+                    .collect(),
+                self.cache_compressed,
+            );
+        };
+    }
+
+    /*- Accept connections until the shutdown flag is set,
+    (never, if there is none) -*/
+    fn serve_listener(&self, listener: TcpListener, shutdown: Option<&AtomicBool>) {
         /*- Initialize thread_handler -*/
-        let thread_handler = thread_handler::MainThreadHandler::new(self.num_threads);
-
-        /*- Stream.incoming() is a blocking iterator. Will unblock on requests -*/
-        for request in stream.incoming() {
-            /*- Spawn a new thread -*/
-            thread_handler.exec(move || {
-                /*- Ignore failing requests -*/
-                handle_req(
-                    match request {
-                        Ok(req) => req,
-                        Err(_) => return,
-                    },
-                    &self,
-                );
+        let thread_handler =
+            thread_handler::MainThreadHandler::new(self.num_threads, &self.thread_name_prefix);
+
+        /*- The config is shared between all worker threads -*/
+        let config: Arc<Server> = Arc::new(self.clone());
+
+        /*- Additional acceptor threads accept connections
+        on their own clones of the listener -*/
+        thread::scope(|scope| {
+            for _ in 1..self.acceptor_threads {
+                if let Ok(listener) = listener.try_clone() {
+                    let (thread_handler, config) = (&thread_handler, &config);
+                    scope.spawn(move || accept(&listener, thread_handler, config, shutdown));
+                };
+            }
+
+            /*- The reaper stops when the server shuts down -*/
+            if let Some(interval) = self.connection_reaper {
+                let connections: &Connections = &config.connections;
+                let timeout: Duration = self.keep_alive.unwrap_or(REAPER_IDLE_TIMEOUT);
+                scope.spawn(move || {
+                    while !shutdown.is_some_and(|shutdown| shutdown.load(Ordering::SeqCst)) {
+                        thread::sleep(interval);
+                        reap_connections(connections, timeout);
+                    }
+                });
+            };
+            accept(&listener, &thread_handler, &config, shutdown);
+        });
+
+        /*- Stop accepting, and let the workers finish -*/
+        if self.logs {
+            println!("Shutting down...");
+        };
+        thread_handler.join(self.shutdown_timeout, self.drain_timeout);
+    }
+}
+
+/// A server which has been bound to its address, but isn't accepting
+/// connections yet. Created using `Server::bind`.
+pub struct BoundServer {
+    server: Server,
+    listener: TcpListener,
+    local_addr: SocketAddr,
+}
+
+impl BoundServer {
+    /// The address the server is bound to, including the port
+    /// which the OS picked if the server was bound to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Start accepting connections. Blocks forever
+    pub fn start(self) {
+        self.server.serve_listener(self.listener, None);
+    }
+
+    /// Start accepting connections, and stop when the process recieves
+    /// Ctrl-C or SIGTERM. See `Server::start_with_shutdown_signal`
+    #[cfg(feature = "signal")]
+    pub fn start_with_shutdown_signal(self) -> Result<(), ConfigError> {
+        /*- The accept loops are blocking, so the handler wakes them
+        up by connecting to the listener after setting the flag -*/
+        let mut wake_addr: SocketAddr = self.local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(match wake_addr {
+                SocketAddr::V4(_) => std::net::Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
             });
-        }
+        };
+        let shutdown: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+        let flag: Arc<AtomicBool> = Arc::clone(&shutdown);
+        let acceptor_threads: u16 = self.server.acceptor_threads;
+        if ctrlc::set_handler(move || {
+            flag.store(true, Ordering::SeqCst);
+            for _ in 0..acceptor_threads {
+                TcpStream::connect(wake_addr).ok();
+            }
+        })
+        .is_err()
+        {
+            return Err(ConfigError::SignalHandlerFail);
+        };
 
-        /*- Return, even though it will never happen -*/
+        self.server.serve_listener(self.listener, Some(&shutdown));
         Ok(())
     }
 }
 
+/*- Accept connections and pass them to the worker threads,
+until the shutdown flag is set, (never, if there is none) -*/
+fn accept(
+    listener: &TcpListener,
+    thread_handler: &thread_handler::MainThreadHandler,
+    config: &Arc<Server>,
+    shutdown: Option<&AtomicBool>,
+) {
+    /*- Stream.incoming() is a blocking iterator. Will unblock on requests -*/
+    for request in listener.incoming() {
+        if shutdown.is_some_and(|shutdown| shutdown.load(Ordering::SeqCst)) {
+            break;
+        };
+        let config = Arc::clone(config);
+
+        /*- Spawn a new thread -*/
+        thread_handler.exec(move || {
+            /*- Ignore failing requests -*/
+            handle_connection(
+                match request {
+                    Ok(req) => req,
+                    Err(_) => return,
+                },
+                &config,
+            );
+        });
+    }
+}
+
 /*- Gets all files in a dir using std::fs -*/
-fn get_list_dir<'a>(dir: &str) -> Vec<String> {
+fn get_list_dir(dir: &str) -> Vec<String> {
     let mut files: Vec<String> = Vec::new();
 
     /*- Get all files in dir -*/
@@ -642,8 +2992,21 @@ fn get_list_dir<'a>(dir: &str) -> Vec<String> {
     files
 }
 
+/*- Check if a file matches any of the cache exclusion patterns -*/
+fn cache_excluded(patterns: &[String], file: &str) -> bool {
+    let path: &Path = Path::new(file);
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_prefix("*.") {
+            Some(extension) => path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case(extension)),
+            None => path.ends_with(pattern.trim_start_matches('/')),
+        })
+}
+
 /*- Loads all files in a dir into memory -*/
-fn load_files_cache(logs: bool, files: Vec<String>) {
+fn load_files_cache(logs: bool, files: Vec<String>, compressed: bool) {
     let files_len = files.len();
     let mut index = 0;
     let mut stdout = std::io::stdout();
@@ -661,18 +3024,22 @@ fn load_files_cache(logs: bool, files: Vec<String>) {
             Ok(e) => e,
             Err(_) => continue,
         };
-        match FILE_CACHE.lock() {
-            Ok(e) => e,
-            Err(_) => continue,
-        }
-        .insert(
-            Path::new(&file)
-                .canonicalize()
-                .unwrap_or(PathBuf::from(""))
-                .display()
-                .to_string(),
-            buf,
-        );
+        let key: String = cache_key(Path::new(&file));
+
+        /*- Files are only stored compressed if it makes them smaller -*/
+        #[cfg(feature = "gzip")]
+        let buf: Vec<u8> = match compressed {
+            true => match utils::encoding::gzip(&buf) {
+                Ok(gzipped) if gzipped.len() < buf.len() => {
+                    gzip_cache().insert(key, gzipped);
+                    stdout.flush().unwrap_or_default();
+                    continue;
+                }
+                _ => buf,
+            },
+            false => buf,
+        };
+        file_cache().insert(key, buf);
         stdout.flush().unwrap_or_default();
     }
     if logs {
@@ -1,2 +1,6 @@
 /*- Public uses (re-export for prelude) -*/
-pub use crate::{Respond, Route, Server, Stream};
+pub use crate::{request::info::Method, response::Cors, Respond, Route, Server, Stream};
+
+/*- Json macro (re-exported from serde_json) -*/
+#[cfg(feature = "serde")]
+pub use serde_json::json;
@@ -5,6 +5,31 @@
 use crate::{stream::Stream, Respond};
 use std::{collections::HashMap, net::TcpStream};
 
+/*- Structs, enums & unions -*/
+/// How request bodies are recieved, set using `Server::body_mode`
+///
+/// ## Example
+/// ```
+/// use responder::prelude::*;
+/// use responder::request::BodyMode;
+///
+/// Server::new()
+///     .port(8080)
+///     .body_mode(BodyMode::Streaming);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyMode {
+    /// The whole body is read before routing, and is
+    /// available using `stream.body()` (the default)
+    Buffered,
+
+    /// The body is left unread, and handlers read it using
+    /// `stream.body_reader()`, `stream.save_body_to()`, or
+    /// `stream.read_body()` to buffer it after all. Useful
+    /// for uploads, which shouldn't be kept in memory
+    Streaming,
+}
+
 /*- Info module -*/
 pub mod info {
     use std::fmt;
@@ -20,6 +45,9 @@ pub mod info {
         /// The path to the endpoint
         pub path: &'lf str,
 
+        /// The query string, (everything after the `?`)
+        pub query: &'lf str,
+
         /// Http verison
         pub version: &'lf str,
     }
@@ -62,7 +90,7 @@ pub mod info {
     impl RequestInfo<'_> {
         /// Parses the request string into valuable information,
         /// like the http-method, path and version
-        pub fn parse_req(request: &str) -> Result<RequestInfo, u8> {
+        pub fn parse_req(request: &str) -> Result<RequestInfo<'_>, u8> {
             /*- Get the lines -*/
             let mut lines = request.split::<&str>("\r\n");
 
@@ -80,6 +108,9 @@ pub mod info {
                 info_str.get(2).unwrap_or(&""),
             );
 
+            /*- The query string is split off, so that routing only sees the path -*/
+            let (path, query): (&str, &str) = path.split_once('?').unwrap_or((path, ""));
+
             /*- Parse the method -*/
             let method = match &*method.to_ascii_uppercase() {
                 "GET" => Method::GET,
@@ -98,11 +129,38 @@ pub mod info {
             Ok(RequestInfo {
                 method,
                 path,
+                query,
                 version,
             })
         }
     }
 
+    impl Method {
+        /// The name of the method, as written in the request line.
+        /// `UNKNOWN` methods return an empty string.
+        ///
+        /// ## Example
+        /// ```
+        /// use responder::prelude::*;
+        ///
+        /// assert_eq!(Method::PATCH.as_str(), "PATCH");
+        /// ```
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Method::GET => "GET",
+                Method::POST => "POST",
+                Method::PUT => "PUT",
+                Method::DELETE => "DELETE",
+                Method::HEAD => "HEAD",
+                Method::OPTIONS => "OPTIONS",
+                Method::CONNECT => "CONNECT",
+                Method::TRACE => "TRACE",
+                Method::PATCH => "PATCH",
+                Method::UNKNOWN => "",
+            }
+        }
+    }
+
     impl std::cmp::PartialEq for Method {
         fn eq(&self, other: &Self) -> bool {
             matches!(
@@ -124,9 +182,56 @@ pub mod info {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             write!(
                 f,
-                "RequestInfo (m: {:?}, p: {}, v: {})",
-                self.method, self.path, self.version
+                "RequestInfo (m: {:?}, p: {}, q: {}, v: {})",
+                self.method, self.path, self.query, self.version
             )
         }
     }
 }
+
+/*- Json module -*/
+#[cfg(feature = "serde")]
+pub mod json {
+    use serde_json::{Map, Value};
+
+    /*- Functions -*/
+    /// Merge `patch` into `target` following the JSON Merge Patch
+    /// rules (RFC 7386). Objects are merged recursively, `null`
+    /// values remove keys and everything else replaces the target.
+    ///
+    /// ## Examples
+    /// ```
+    /// use responder::merge_json;
+    /// use serde_json::json;
+    ///
+    /// let mut user = json!({ "name": "John", "age": 30, "address": { "city": "Stockholm" } });
+    /// merge_json(&mut user, &json!({ "age": null, "address": { "zip": "123 45" } }));
+    ///
+    /// assert_eq!(user, json!({ "name": "John", "address": { "city": "Stockholm", "zip": "123 45" } }));
+    /// ```
+    pub fn merge_json(target: &mut Value, patch: &Value) {
+        /*- Non-object patches replace the target entirely -*/
+        let patch = match patch {
+            Value::Object(patch) => patch,
+            _ => {
+                *target = patch.clone();
+                return;
+            }
+        };
+
+        /*- Object patches can only be merged into objects -*/
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        };
+
+        if let Value::Object(target) = target {
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(key);
+                } else {
+                    merge_json(target.entry(key).or_insert(Value::Null), value);
+                }
+            }
+        };
+    }
+}
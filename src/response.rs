@@ -1,6 +1,8 @@
 /*- Imports -*/
 use crate::{stream::Stream, Server};
+use lazy_static::lazy_static;
 use std::{
+    collections::HashMap,
     fs,
     io::{Read, Write},
     path::Path,
@@ -72,6 +74,24 @@ pub const STATUS_CODES: &[(&u16, &str); 58] = &[
     (&226, "IM Used"),
 ];
 
+/*- Informational statuses, which aren't in `STATUS_CODES`
+so that its length (and therefore its type) stays the same -*/
+const INFORMATIONAL_STATUS_CODES: &[(&u16, &str)] = &[
+    (&100, "Continue"),
+    (&101, "Switching Protocols"),
+    (&103, "Early Hints"),
+];
+
+/*- Status codes are looked up on every response, so
+the table is turned into a hashmap once -*/
+lazy_static! {
+    static ref REASON_PHRASES: HashMap<u16, &'static str> = STATUS_CODES
+        .iter()
+        .chain(INFORMATIONAL_STATUS_CODES)
+        .map(|(status, reason)| (**status, *reason))
+        .collect();
+}
+
 /*- Structs, enums & unions -*/
 #[derive(Clone, Debug)]
 /// The respond struct will mostly be constructed by using the builder
@@ -89,6 +109,9 @@ pub struct Respond {
     pub response_type: ResponseType,
     pub content: Option<String>,
     pub additional_headers: Option<Vec<String>>,
+
+    /// The http-status, used by `stream.send()`
+    pub status: Option<u16>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -100,9 +123,87 @@ pub enum ResponseType {
     Html,
     Js,
     Image(ImageType),
+
+    /// Arbitrary binary data, (`application/octet-stream`)
+    OctetStream,
+
+    /// Server-sent events, (`text/event-stream`)
+    EventStream,
     Custom(&'static str),
 }
 
+/// Cross-origin resource sharing headers which are sent with every
+/// response. Enabled server-wide using `Server::cors()`, or for the
+/// routes inside of a `Route::CorsStack`.
+///
+/// ## Examples
+/// ```
+/// use responder::prelude::*;
+///
+/// let routes = &[
+///     Route::CorsStack(Some(Cors::PERMISSIVE), "api", &[
+///         Route::Get("data", |stream| {}),
+///     ]),
+///     Route::CorsStack(Some(Cors { allow_origin: "https://example.com", ..Cors::PERMISSIVE }), "private", &[
+///         Route::Get("data", |stream| {}),
+///     ]),
+/// ];
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Cors {
+    /// The `Access-Control-Allow-Origin` header
+    pub allow_origin: &'static str,
+
+    /// The `Access-Control-Allow-Headers` header
+    pub allow_headers: &'static str,
+
+    /// The `Access-Control-Allow-Methods` header
+    pub allow_methods: &'static str,
+
+    /// The `Access-Control-Max-Age` header, in seconds
+    pub max_age: u32,
+}
+
+/// The body format of error responses which the server sends itself,
+/// (like 404 and 405) or which are sent using `stream.respond_status`
+/// without a body. Set using `Server::error_format`
+///
+/// ## Example
+/// ```
+/// use responder::prelude::*;
+/// use responder::response::ErrorFormat;
+///
+/// /* 404 responses will look like this: {"status":404,"error":"Not Found"} */
+/// Server::new().error_format(ErrorFormat::Json);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorFormat {
+    /// Plain text, (ex `404 Not Found`) which is the default
+    Text,
+
+    /// JSON, (ex `{"status":404,"error":"Not Found"}`)
+    Json,
+}
+
+/// What `stream.respond_template` does with placeholders which
+/// aren't in the context. Set using `Server::missing_template_keys`
+///
+/// ## Example
+/// ```
+/// use responder::prelude::*;
+/// use responder::response::MissingKeys;
+///
+/// Server::new().missing_template_keys(MissingKeys::Error);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MissingKeys {
+    /// Render them empty, (the default)
+    Empty,
+
+    /// Respond with `500 Internal Server Error` instead
+    Error,
+}
+
 /// What type of image server will respond with
 #[derive(Clone, Copy, Debug)]
 pub enum ImageType {
@@ -114,11 +215,22 @@ pub enum ImageType {
 }
 
 /*- Functions -*/
+/*- Informational (1xx), 204 (No Content) and 304 (Not Modified)
+responses must not have a body, (clients won't read one) -*/
+pub(crate) fn allows_body(status: u16) -> bool {
+    !matches!(status, 100..=199 | 204 | 304)
+}
+
+/*- The reason phrase of a status code, if it's known -*/
+pub(crate) fn reason_phrase(status: u16) -> Option<&'static str> {
+    REASON_PHRASES.get(&status).copied()
+}
+
 /*- Send 404 page -*/
 /// Respond with a 404 page, will firstly check
 /// if `config.not_found` exists, and grab 404 page path
 /// from there, else it will just send 404 as a status code
-pub fn not_found(stream: &mut Stream, config: Server) {
+pub fn not_found(stream: &mut Stream, config: &Server) {
     /*- If 404 page is provided -*/
     if let Some(page) = config.not_found {
         stream.respond_file(404u16, page);
@@ -128,46 +240,125 @@ pub fn not_found(stream: &mut Stream, config: Server) {
 }
 
 /*- Method implementations -*/
+impl Cors {
+    /// Allow requests from all origins, with all headers and methods
+    pub const PERMISSIVE: Cors = Cors {
+        allow_origin: "*",
+        allow_headers: "*",
+        allow_methods: "GET, POST, PUT, DELETE, OPTIONS, HEAD",
+        max_age: 86400,
+    };
+
+    /*- The headers, each one prefixed with a CRLF -*/
+    pub(crate) fn headers(&self) -> String {
+        format!(
+            "\r\nAccess-Control-Allow-Origin: {}\r\nAccess-Control-Allow-Headers: {}\r\nAccess-Control-Allow-Methods: {}\r\nAccess-Control-Max-Age: {}",
+            self.allow_origin, self.allow_headers, self.allow_methods, self.max_age
+        )
+    }
+}
 impl ResponseType {
+    /// The mime type which is written in the `Content-Type` header
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ResponseType::Json => "application/json",
+            ResponseType::Js => "text/javascript",
+            ResponseType::Text => "text/plain",
+            ResponseType::Html => "text/html",
+            ResponseType::Css => "text/css",
+            ResponseType::Image(c) => match c {
+                ImageType::Jpeg => "image/jpeg",
+                ImageType::Png => "image/png",
+                ImageType::Gif => "image/gif",
+                ImageType::Webp => "image/webp",
+                ImageType::Svg => "image/svg+xml",
+            },
+            ResponseType::OctetStream => "application/octet-stream",
+            ResponseType::EventStream => "text/event-stream",
+            ResponseType::Custom(custom) => custom,
+        }
+    }
+
     /*- Guesses which response type a file should have -*/
     pub fn guess(path: &Path) -> Self {
-        let path: &Path = Path::new(path);
-
-        /*- Check extensions -*/
-        match path.extension() {
-            Some(ext) => {
-                match ext.to_str() {
-                    /*- Html -*/
-                    Some("html") => ResponseType::Html,
-                    Some("htm") => ResponseType::Html,
-
-                    /*- Json -*/
-                    Some("json") => ResponseType::Json,
-                    Some("yml") => ResponseType::Json,
-                    Some("yaml") => ResponseType::Json,
-
-                    /*- Css -*/
-                    Some("css") => ResponseType::Css,
-
-                    /*- Js -*/
-                    Some("js") => ResponseType::Js,
-
-                    /*- Image -*/
-                    Some("png") => ResponseType::Image(ImageType::Png),
-                    Some("jpg") => ResponseType::Image(ImageType::Jpeg),
-                    Some("jpeg") => ResponseType::Image(ImageType::Jpeg),
-                    Some("gif") => ResponseType::Image(ImageType::Gif),
-                    Some("webp") => ResponseType::Image(ImageType::Webp),
-                    Some("svg") => ResponseType::Image(ImageType::Svg),
-
-                    /*- Text -*/
-                    Some(_) => ResponseType::Text,
-                    None => ResponseType::Text,
-                }
-            }
+        match Self::from_extension(path) {
+            Some(e) => e,
+
+            /*- Unknown extensions might be binary, which
+            browsers would try to display as text otherwise -*/
+            None if path.extension().is_some() => ResponseType::OctetStream,
             None => ResponseType::Text,
         }
     }
+
+    /*- The response type of a file extension, None if it's unknown or missing -*/
+    pub(crate) fn from_extension(path: &Path) -> Option<Self> {
+        Some(match path.extension()?.to_str()? {
+            /*- Html -*/
+            "html" => ResponseType::Html,
+            "htm" => ResponseType::Html,
+
+            /*- Json -*/
+            "json" => ResponseType::Json,
+            "yml" => ResponseType::Json,
+            "yaml" => ResponseType::Json,
+
+            /*- Css -*/
+            "css" => ResponseType::Css,
+
+            /*- Js -*/
+            "js" => ResponseType::Js,
+
+            /*- Image -*/
+            "png" => ResponseType::Image(ImageType::Png),
+            "jpg" => ResponseType::Image(ImageType::Jpeg),
+            "jpeg" => ResponseType::Image(ImageType::Jpeg),
+            "gif" => ResponseType::Image(ImageType::Gif),
+            "webp" => ResponseType::Image(ImageType::Webp),
+            "svg" => ResponseType::Image(ImageType::Svg),
+            "ico" => ResponseType::Custom("image/x-icon"),
+
+            /*- Text -*/
+            "txt" | "md" | "csv" | "log" => ResponseType::Text,
+            "xml" => ResponseType::Custom("application/xml"),
+            _ => return None,
+        })
+    }
+
+    /// Guess the response type of a file by its first bytes, (its magic
+    /// number) for files where the extension doesn't tell. Detects PNG,
+    /// JPEG, GIF, PDF, gzip and WebAssembly files.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::response::ResponseType;
+    ///
+    /// let response_type = ResponseType::guess_bytes(b"GIF89a...").unwrap();
+    /// assert_eq!(response_type.content_type(), "image/gif");
+    /// assert!(ResponseType::guess_bytes(b"plain text").is_none());
+    /// ```
+    pub fn guess_bytes(start: &[u8]) -> Option<Self> {
+        if start.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ResponseType::Image(ImageType::Png))
+        } else if start.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ResponseType::Image(ImageType::Jpeg))
+        } else if start.starts_with(b"GIF87a") || start.starts_with(b"GIF89a") {
+            Some(ResponseType::Image(ImageType::Gif))
+        } else if start.starts_with(b"%PDF-") {
+            Some(ResponseType::Custom("application/pdf"))
+        } else if start.starts_with(&[0x1F, 0x8B]) {
+            Some(ResponseType::Custom("application/gzip"))
+        } else if start.starts_with(b"\0asm") {
+            Some(ResponseType::Custom("application/wasm"))
+        } else {
+            None
+        }
+    }
+}
+impl Default for Respond {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl Respond {
     /// Construct a request struct
@@ -176,6 +367,26 @@ impl Respond {
             response_type: ResponseType::Text,
             content: None,
             additional_headers: None,
+            status: None,
+        }
+    }
+
+    /// Construct a request struct with an empty body. Unlike `Respond::new()`,
+    /// which responds with the status as text (like "200 OK"), this
+    /// will respond with `Content-Length: 0` and no body at all.
+    ///
+    /// ## Examples
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Respond::empty().headers(vec!["X-Request-Id: 1234".into()]);
+    /// ```
+    pub fn empty() -> Self {
+        Respond {
+            response_type: ResponseType::Text,
+            content: Some(String::new()),
+            additional_headers: None,
+            status: None,
         }
     }
 
@@ -234,6 +445,20 @@ impl Respond {
         }
     }
 
+    /// Set the http-status, so the `Respond` struct is a complete
+    /// response which can be sent using `stream.send()`
+    ///
+    /// ## Examples
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// Respond::new().status(201u16).json("{\"id\":12}");
+    /// ```
+    pub fn status(&mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self.clone()
+    }
+
     /// Set additional headers
     pub fn headers(&mut self, headers: Vec<String>) -> Self {
         self.additional_headers = Some(headers);
@@ -1,20 +1,50 @@
 /*- Imports -*/
 use crate::{
-    response::{ImageType, Respond, ResponseType, STATUS_CODES},
-    FILE_CACHE,
+    cached_file, parse_chunk_size,
+    request::info::{Method, RequestInfo},
+    response::{allows_body, reason_phrase, Cors, ErrorFormat, MissingKeys, Respond, ResponseType},
+    utils::{date, headers, html, json, url},
+    CHUNK_LINE_MAX, TRAILERS_MAX,
 };
 use std::{
+    any::{Any, TypeId},
+    cell::OnceCell,
     collections::HashMap,
     fs::File,
     hash::Hash,
-    io::Read,
-    io::Write,
-    net::TcpStream,
+    io::{BufWriter, Cursor, ErrorKind, Read, Seek, SeekFrom, Write},
+    net::{Shutdown, TcpStream},
     path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-/*- TEMP Cors -*/
-const CORS:&'static str = "\r\nAccess-Control-Allow-Origin: *\r\nAccess-Control-Allow-Headers: *\r\nAccess-Control-Allow-Methods: GET, POST, PUT, DELETE, OPTIONS, HEAD\r\nAccess-Control-Max-Age: 86400";
+#[cfg(feature = "serde")]
+use crate::errors::JsonError;
+#[cfg(feature = "proxy")]
+use crate::errors::ProxyError;
+use crate::errors::{CharsetError, ParamError};
+#[cfg(feature = "gzip")]
+use crate::{cached_gzip_file, utils::encoding};
+
+/*- Constants -*/
+const CHUNK_READ: usize = 8192usize;
+/*- Headers which only apply to the connection they're sent on, (RFC 9110 7.6.1)
+so `proxy_to` doesn't forward them. Host and Content-Length are set by it -*/
+#[cfg(feature = "proxy")]
+const PROXY_SKIPPED_HEADERS: &[&str] = &[
+    "host",
+    "content-length",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
 
 /*- Structs, enums & unions -*/
 /// A simple wrapper for the TcpStream struct, which we want because
@@ -26,23 +56,137 @@ const CORS:&'static str = "\r\nAccess-Control-Allow-Origin: *\r\nAccess-Control-
 pub struct Stream<'lf> {
     /// We won't take a mutable reference of TcpStream because we want
     /// full ownership of it which will give us mutable access to it anyways.
-    stream_inner: TcpStream,
+    /// Writes are buffered, and flushed at the end of each response
+    stream_inner: BufWriter<TcpStream>,
 
     /// If stream_inner has aleady been written to (Should only be written to once)
     buf_written_to: bool,
 
+    /// The status of the response, once one has been sent
+    status: Option<u16>,
+
     /// Body is only used in POST requests. Often used for sending & recieving
     /// big chunks of data like images or files.
     body: String,
 
+    /// The body as it was recieved, before being converted into a string
+    raw_body: Vec<u8>,
+
     /// URL-parameters which will be set in routes by using :_: in tail
     params: HashMap<String, String>,
 
     /// Header keys and values which will specified in fetch requests
-    headers: HashMap<&'lf str, &'lf str>,
+    headers: Vec<(&'lf str, &'lf str)>,
+
+    /// The headers as a hashmap, which is only built if requested
+    headers_map: OnceCell<HashMap<&'lf str, &'lf str>>,
+
+    /// The http-method of the request
+    method: Method,
+
+    /// The requested path, without the `?query` (see `query`)
+    path: &'lf str,
+
+    /// The query string of the request line, (without the `?`)
+    query: &'lf str,
+
+    /// When the request started being processed
+    start: Instant,
+
+    /// When the request should be finished by (set from the server config)
+    deadline: Option<Instant>,
+
+    /// Cors headers, if enabled for the server or the matched route
+    cors: Option<Cors>,
+
+    /// If writing the response failed, (like when the client disconnected)
+    disconnected: bool,
+
+    /// If failed writes should be logged
+    logs: bool,
+
+    /// Creates the html body of redirects (set from the server config)
+    redirect_body: Option<fn(&str) -> String>,
+
+    /// Headers which are sent with every response (set from the server config)
+    default_headers: &'lf [(String, String)],
+
+    /// If response header names are written in Title-Case (set from the server config)
+    canonicalize_headers: bool,
+
+    /// Overrides the reason phrase of the status line
+    reason: Option<String>,
+
+    /// If the connection can be reused for another request after this one
+    keep_alive: bool,
+
+    /// The request line and headers, as they were recieved
+    raw_request: &'lf str,
+
+    /// Headers sent after a chunked request body
+    trailers: Vec<(String, String)>,
+
+    /// The body which hasn't been read yet, (only when streaming bodies)
+    unread_body: Option<UnreadBody>,
+
+    /// The path of the matched route, including stack prefixes
+    route_template: Option<String>,
+
+    /// Request headers which the response depends on, sent as `Vary`
+    vary: Vec<&'static str>,
+
+    /// The type of files which can't be guessed (set from the server config)
+    default_content_type: Option<ResponseType>,
+
+    /// The body format of status responses (set from the server config)
+    error_format: ErrorFormat,
+
+    /// The html template of error responses (set from the server config)
+    error_page_template: Option<&'static str>,
+
+    /// What templates do with missing keys (set from the server config)
+    missing_keys: MissingKeys,
+
+    /// Values attached to the request, one per type
+    extensions: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+
+    /// The start of the response body, kept for the audit log
+    captured_response: Vec<u8>,
+
+    /// How much of the response body is kept (set from the server config)
+    capture_limit: usize,
+}
+
+/// Reads the request body. Bodies which are streamed (see `BodyMode`)
+/// are read straight from the connection, and buffered bodies from
+/// memory. Created using `stream.body_reader()`.
+pub struct BodyReader<'s, 'lf> {
+    stream: &'s mut Stream<'lf>,
+    pos: usize,
+}
 
-    /// Cors
-    cors: bool,
+/*- A request body which hasn't been read from the connection yet -*/
+pub(crate) struct UnreadBody {
+    /*- Bytes which have been recieved, but not consumed yet -*/
+    buffered: Vec<u8>,
+    pos: usize,
+    framing: Framing,
+
+    /*- Decoded body bytes read so far, and the limit -*/
+    read: usize,
+    max: Option<usize>,
+}
+
+/*- How the end of an unread body is found -*/
+pub(crate) enum Framing {
+    /*- The number of bytes left, from Content-Length -*/
+    Length(u64),
+
+    /*- The bytes left of the current chunk, and if it's the first one -*/
+    Chunked(u64, bool),
+
+    /*- The whole body has been read -*/
+    Done,
 }
 
 /*- Method implementations -*/
@@ -68,365 +212,1747 @@ impl<'a> Stream<'a> {
             return;
         };
         self.buf_written_to = true;
+        self.status = Some(status);
 
-        /*- Get the status string -*/
-        let status_msg = STATUS_CODES
-            .iter()
-            .find(|&x| x.0 == &status)
-            .unwrap_or(&(&status, "Internal error - Missing status code"))
-            .1;
-
-        /*- Get the response type -*/
-        let mut response_type: &str = match respond.response_type {
-            ResponseType::Json => "application/json",
-            ResponseType::Js => "text/javascript",
-            ResponseType::Text => "text/plain",
-            ResponseType::Html => "text/html",
-            ResponseType::Css => "text/css",
-            ResponseType::Image(c) => match c {
-                ImageType::Jpeg => "image/jpeg",
-                ImageType::Png => "image/png",
-                ImageType::Gif => "image/gif",
-                ImageType::Webp => "image/webp",
-                ImageType::Svg => "image/svg+xml",
-            },
-            ResponseType::Custom(custom) => &custom,
-        };
-        let cors = if self.cors { CORS } else { "" };
-
-        /*- If content was provided -*/
-        if let Some(content) = respond.content {
-            /*- Grab additional headers -*/
-            let additional_headers = match respond.additional_headers {
-                Some(headers) => vec!["\r\n", &headers.join("\r\n")].join(""),
-                None => String::new(),
-            };
-
+        /*- 1xx, 204 and 304 responses never have a body -*/
+        if !allows_body(status) {
+            let head = self.head(status, respond.additional_headers.unwrap_or_default());
+            self.write_inner(head.as_bytes());
+        } else if let Some(content) = respond.content {
             /*- Write the status & content to the stream -*/
-            if self.stream_inner.write(
-                format!(
-                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Type: {}{additional_headers}{cors}\r\n\r\n{content}",
-                    status, content.len(), response_type
-                ).as_bytes()
-            ).is_ok() { };
+            let head = self.content_head(
+                status,
+                respond.response_type,
+                content.len() as u64,
+                respond.additional_headers,
+            );
+            self.write_response(head, content.as_bytes());
         } else {
             /*- Write the status to the stream -*/
-            if self
-                .stream_inner
-                .write(
-                    format!("HTTP/1.1 {}{cors}\r\n\r\n{} {}", status, status, status_msg)
-                        .as_bytes(),
-                )
-                .is_ok()
-            {};
+            let mut headers: Vec<String> = Vec::new();
+            let content: String = match self.error_format {
+                ErrorFormat::Json if status >= 400 => {
+                    headers.push(String::from("Content-Type: application/json"));
+                    format!(
+                        "{{\"status\":{},\"error\":\"{}\"}}",
+                        status,
+                        json::escape(self.status_msg(status))
+                    )
+                }
+                _ => match self.error_page(status) {
+                    Some(page) => {
+                        headers.push(format!(
+                            "Content-Type: {}",
+                            ResponseType::Html.content_type()
+                        ));
+                        page
+                    }
+                    None => format!("{} {}", status, self.status_msg(status)),
+                },
+            };
+            headers.push(format!("Content-Length: {}", content.len()));
+            headers.extend(respond.additional_headers.unwrap_or_default());
+            let head = self.head(status, headers);
+            self.write_response(head, content.as_bytes());
         };
 
         /*- Flush the stream -*/
-        self.stream_inner.flush().ok();
+        self.flush_inner();
     }
 
-    /// Respond with just status code
-    /// ## Example
-    /// ```
-    /// use responder::prelude::*;
-    ///
-    /// /* Repond with 200 (OK) */
-    /// fn endpoint_200(stream:&mut Stream) -> () {
-    ///     stream.respond_status(200u16); // Response body will look like this: "200 OK"
-    /// }
-    ///
-    /// /* Repond with 600 (status code doesn't exist) */
-    /// fn endpoint_600(stream:&mut Stream) -> () {
-    ///     stream.respond_status(600u16); // Response body will look like this: "600 Internal error - Missing status code"
-    /// }
-    /// ```
-    pub fn respond_status(&mut self, status: u16) {
+    /*- Respond with the contents of a reader, without buffering it in memory -*/
+    pub(crate) fn respond_reader(
+        &mut self,
+        status: u16,
+        response_type: ResponseType,
+        length: u64,
+        reader: impl Read,
+        headers: Option<Vec<String>>,
+    ) {
         /*- Check buffer write access -*/
         if self.buf_written_to {
             return;
         };
         self.buf_written_to = true;
+        self.status = Some(status);
 
-        /*- Get the status string -*/
-        let status_msg = STATUS_CODES
-            .iter()
-            .find(|&x| x.0 == &status)
-            .unwrap_or(&(&status, "Internal error - Missing status code"))
-            .1;
-        let cors = if self.cors { CORS } else { "" };
-
-        /*- Get the response type -*/
-        let mut response_type: &str = "text/plain";
-
-        /*- Write the status to the stream -*/
-        if self
-            .stream_inner
-            .write(format!("HTTP/1.1 {}{cors}\r\n\r\n{} {}", status, status, status_msg).as_bytes())
-            .is_ok()
-        {};
+        /*- Write the head, and then pump the body in chunks -*/
+        if !allows_body(status) {
+            let head = self.head(status, headers.unwrap_or_default());
+            self.write_inner(head.as_bytes());
+        } else if self.write_inner(
+            self.content_head(status, response_type, length, headers)
+                .as_bytes(),
+        ) && self.method != Method::HEAD
+        {
+            /*- If the reader ended early, (like a file being truncated while
+            sending it) the client can't know where the response ends -*/
+            if self.pump(reader.take(length)).ok() != Some(length) {
+                self.keep_alive = false;
+            };
+        };
 
         /*- Flush the stream -*/
-        self.stream_inner.flush().ok();
+        self.flush_inner();
     }
 
-    /// Respond with JSON payload, takes `payload` as param, which can be any type that can be converted into a `String`
+    /// Send a response, with the status set using `Respond::status`,
+    /// (200 if it wasn't set). Lets functions build whole responses,
+    /// which the handler then sends.
+    ///
     /// ## Example
     /// ```
     /// use responder::prelude::*;
     ///
-    /// /* Repond with some JSON values (TIP: use the `json!()` macro from the `serde_json` crate) */
-    /// fn endpoint_payload(stream:&mut Stream) -> () {
-    ///     stream.payload("{{\"key\": \"value\"}}");
+    /// fn create_user(name: &str) -> Respond {
+    ///     match name.is_empty() {
+    ///         true => Respond::new().status(400u16).text("name is required"),
+    ///         false => Respond::new().status(201u16).json("{\"created\":true}"),
+    ///     }
     /// }
-    /// ```
-    pub fn payload(&mut self, payload: impl ToString) {
-        self.respond(200, Respond::new().json(&payload.to_string()));
-    }
-
-    /// Respond with JSON payload status
-    /// ## Example
-    /// ```
-    /// use responder::prelude::*;
     ///
-    /// /* Repond with payload status (real http status will be 200) */
-    /// fn endpoint(stream:&mut Stream) -> () {
-    ///     stream.payload_status(200);
+    /// fn endpoint(stream: &mut Stream) {
+    ///     let response: Respond = create_user(stream.body());
+    ///     stream.send(response);
     /// }
     /// ```
-    ///
-    /// ## Usecases
-    /// In JavaScript, you often want payload status responses instead of http-status responses, for example
-    /// you have a backend API with diffrent user-profiles. And on the client-side some user searches for a
-    /// non-existing user. We'd like to respond with a payload status of 404, not http-status 404 because that
-    /// would make JavaScript think that the route specified in the fetch request is invalid, eventhough it's
-    /// valid - only that the user wasn't found.
-    pub fn payload_status(&mut self, status: u16) {
-        self.payload(format!("{{\"status\":{}}}", status));
+    pub fn send(&mut self, respond: Respond) {
+        self.respond(respond.status.unwrap_or(200u16), respond);
     }
 
-    /// Get a mutable reference of the inner stream because
-    /// the stream_inner key isn't exposed publicly. Gives
-    /// access to more in-depth functionality
+    /// Respond with binary data, like an image or protobuf message
+    /// generated by the handler. `Respond` content is always text,
+    /// so this is used for bytes which might not be valid utf-8.
     ///
-    /// ## Examples
+    /// ## Example
     /// ```
     /// use responder::prelude::*;
+    /// use responder::response::{ImageType, ResponseType};
     ///
-    /// fn endpoint(stream:&mut Stream) -> () {
-    ///     stream.get_mut_inner_ref(); // -> &mut TcpStream
+    /// fn endpoint(stream: &mut Stream) {
+    ///     let pixel: [u8; 4] = [0x89, 0x50, 0x4E, 0x47];
+    ///     stream.respond_bytes(200u16, &pixel, ResponseType::Image(ImageType::Png));
     /// }
     /// ```
-    pub fn get_mut_inner_ref(&mut self) -> &mut TcpStream {
-        &mut self.stream_inner
+    pub fn respond_bytes(&mut self, status: u16, bytes: &[u8], response_type: ResponseType) {
+        self.respond_reader(status, response_type, bytes.len() as u64, bytes, None);
     }
 
-    /// Redirect requests to url, might not work with all browsers so
-    /// a link will appear which users can click incase it doesn't work.
+    /// Respond with chunks from an iterator, without knowing the full
+    /// length of the body beforehand. Chunks are written as they are
+    /// yielded (using chunked transfer encoding), so memory usage stays
+    /// flat no matter how big the response is. Stops iterating if the
+    /// client disconnects.
     ///
-    /// ## Examples
+    /// ## Example
     /// ```
     /// use responder::prelude::*;
+    /// use responder::response::ResponseType;
     ///
-    /// fn redirect_user(stream:&mut Stream) -> () {
-    ///     stream.redirect("https://google.com");
+    /// fn endpoint(stream: &mut Stream) {
+    ///     let rows = (0..1000).map(|i| format!("{i},row {i}\n").into_bytes());
+    ///     stream.respond_iter(200u16, ResponseType::Custom("text/csv"), rows);
     /// }
     /// ```
-    pub fn redirect(&mut self, url: &str) -> () {
-        self.respond(
-            308u16,
-            Respond::new()
-                .html(
-                    &format!(
-                        "<html><head><meta http-equiv=\"refresh\" content=\"0; url={}\" /></head><body><a href=\"{}\">Click here if you are not redirected</a></body></html>",
-                        url,
-                        url
-                    )
-                )
-                .headers(vec![format!("Location: {}", url)])
+    pub fn respond_iter(
+        &mut self,
+        status: u16,
+        response_type: ResponseType,
+        chunks: impl IntoIterator<Item = Vec<u8>>,
+    ) {
+        self.respond_chunked(
+            status,
+            response_type,
+            chunks.into_iter().map(Ok),
+            &[],
+            Vec::new,
         );
     }
 
-    /*- Append request data (body, headers, url-params) to self -*/
-    /// Builder pattern for making the `Stream` struct. Not meant to be used
-    pub fn set_body(&mut self, body: String) -> &mut Self {
-        self.body = body;
-        self
-    }
-    /// Builder pattern for making the `Stream` struct. Not meant to be used
-    pub fn set_headers(&mut self, headers: HashMap<&'a str, &'a str>) -> &mut Self {
-        self.headers = headers;
-        self
-    }
-    /// Builder pattern for making the `Stream` struct. Not meant to be used
-    pub fn set_params(&mut self, params: HashMap<String, String>) -> &mut Self {
-        self.params = params;
-        self
-    }
-
-    /// Require headers to be specified. If they are not, this
-    /// function will repsond with an array containing missing
-    /// headers. Return true indicating that the request should
-    /// be cancelled or not.
+    /// Like `respond_iter`, but with trailers, (headers which are sent after
+    /// the body). The names of the trailers are declared up front using the
+    /// `Trailer` header, and `trailers` is called once all chunks have been
+    /// written, (ex for sending a checksum of the body).
     ///
-    /// ## Examples
+    /// ## Example
     /// ```
     /// use responder::prelude::*;
+    /// use responder::response::ResponseType;
+    /// use std::cell::Cell;
     ///
-    /// /*- Return if headers were not specified -*/
-    /// fn endpoint(stream:&mut Stream) -> () {
-    ///     if stream.expect_headers(&["authentification"]) { return; };
+    /// fn endpoint(stream: &mut Stream) {
+    ///     let written: Cell<usize> = Cell::new(0);
+    ///     let rows = (0..1000)
+    ///         .map(|i| format!("{i},row {i}\n").into_bytes())
+    ///         .inspect(|row| written.set(written.get() + row.len()));
     ///
-    ///     /* Handle authorized requests... */
+    ///     stream.respond_iter_with_trailers(
+    ///         200u16,
+    ///         ResponseType::Custom("text/csv"),
+    ///         rows,
+    ///         &["X-Rows-Length"],
+    ///         || vec![format!("X-Rows-Length: {}", written.get())],
+    ///     );
     /// }
     /// ```
-    pub fn expect_headers(&mut self, headers: &[&str]) -> bool {
-        let request_headers: Vec<&&str> = self.headers().keys().collect();
+    pub fn respond_iter_with_trailers(
+        &mut self,
+        status: u16,
+        response_type: ResponseType,
+        chunks: impl IntoIterator<Item = Vec<u8>>,
+        trailer_names: &[&str],
+        trailers: impl FnOnce() -> Vec<String>,
+    ) {
+        self.respond_chunked(
+            status,
+            response_type,
+            chunks.into_iter().map(Ok),
+            trailer_names,
+            trailers,
+        );
+    }
 
-        for expected_header in headers {
-            if !request_headers.contains(&expected_header) {
-                self.respond(
-                    400u16,
-                    Respond::new().text(&format!(
-                        "This endpoint requires these headers: {headers:?}"
-                    )),
-                );
-                return true;
-            }
-        }
+    /*- Write a response using chunked transfer encoding,
+    followed by trailers if any have been declared -*/
+    fn respond_chunked(
+        &mut self,
+        status: u16,
+        response_type: ResponseType,
+        chunks: impl IntoIterator<Item = std::io::Result<Vec<u8>>>,
+        trailer_names: &[&str],
+        trailers: impl FnOnce() -> Vec<String>,
+    ) {
+        /*- Check buffer write access -*/
+        if self.buf_written_to {
+            return;
+        };
+        self.buf_written_to = true;
+        self.status = Some(status);
 
-        false
-    }
+        /*- 1xx, 204 and 304 responses never have a body -*/
+        if !allows_body(status) {
+            let head = self.head(status, Vec::new());
+            self.write_inner(head.as_bytes());
+            return self.flush_inner();
+        };
 
-    /// Require headers to be specified, but ignore encapsulation. If
-    /// headers are not set, this function will repsond with an array
-    /// containing missing headers. Return true indicating that the
-    /// request should be cancelled or not.
-    ///
-    /// ## Examples
-    /// ```
-    /// use responder::prelude::*;
-    ///
-    /// /*- Return if headers were not specified -*/
-    /// fn endpoint(stream:&mut Stream) -> () {
-    ///     if stream.expect_headers(&["authentification"]) { return; };
-    ///
-    ///     /* Handle authorized requests... */
-    /// }
-    /// ```
-    pub fn expect_headers_ignore_caps(&mut self, headers: &[&str]) -> bool {
-        let request_headers: Vec<String> = self
-            .headers
-            .keys()
-            .collect::<Vec<&&str>>()
-            .iter()
-            .map(|e| e.to_ascii_lowercase())
-            .collect();
+        let mut headers: Vec<String> = vec![
+            format!("Content-Type: {}", response_type.content_type()),
+            String::from("Transfer-Encoding: chunked"),
+        ];
+        if !trailer_names.is_empty() {
+            headers.push(format!("Trailer: {}", trailer_names.join(", ")));
+        };
+        let head = self.head(status, headers);
+        if !self.write_inner(head.as_bytes()) || self.method == Method::HEAD {
+            return self.flush_inner();
+        };
 
-        for expected_header in headers {
-            if !request_headers.contains(&expected_header.to_ascii_lowercase()) {
-                self.respond(
-                    400u16,
-                    Respond::new().text(&format!(
-                        "This endpoint requires these headers: {headers:?}"
-                    )),
-                );
-                return true;
-            }
+        /*- Empty chunks would end the body early, so they're skipped -*/
+        for chunk in chunks {
+            /*- The status has already been sent, so if a chunk can't be produced
+            the connection is closed, and the client sees an incomplete body -*/
+            let chunk: Vec<u8> = match chunk {
+                Ok(e) => e,
+                Err(e) => {
+                    if self.logs {
+                        eprintln!("[responder] Aborted chunked response: {e}");
+                    };
+                    self.flush_inner();
+                    return self.close();
+                }
+            };
+            if chunk.is_empty() {
+                continue;
+            };
+            self.capture(&chunk);
+            let framed: Vec<u8> =
+                [format!("{:x}\r\n", chunk.len()).as_bytes(), &chunk, b"\r\n"].concat();
+            if !self.write_inner(&framed) {
+                return;
+            };
         }
 
-        false
+        /*- The last chunk, followed by the trailers. Line breaks are
+        removed from them, like in the head -*/
+        let mut last_chunk: String = String::from("0\r\n");
+        if !trailer_names.is_empty() {
+            for trailer in trailers() {
+                self.push_header(&mut last_chunk, &trailer);
+                last_chunk.push_str("\r\n");
+            }
+        };
+        last_chunk.push_str("\r\n");
+        self.write_inner(last_chunk.as_bytes());
+        self.flush_inner();
     }
 
-    /*- Respond with file -*/
-    /// ## Example
-    /// ```
-    /// use responder::prelude::*;
-    ///
-    /// fn endpoint(stream:&mut Stream) -> () {
-    ///     stream.respond_file(200u16, "/path/to/file.png")
-    /// }
-    /// ```
-    pub fn respond_file(&mut self, status: u16, path: &str) -> () {
-        /*- Grab the path -*/
-        let _path = Path::new(path);
+    /*- Respond with a file. Files with a modification time get ETag and
+    Last-Modified headers, and 200 responses honor single byte Range requests.
+    If-Range is respected, so a changed file is sent in full instead -*/
+    pub(crate) fn respond_file_reader(
+        &mut self,
+        status: u16,
+        response_type: ResponseType,
+        length: u64,
+        modified: Option<SystemTime>,
+        mut reader: impl Read + Seek,
+    ) {
+        let etag: Option<String> = modified.map(|modified| {
+            let secs = modified
+                .duration_since(UNIX_EPOCH)
+                .map(|e| e.as_secs())
+                .unwrap_or(0);
+            format!("\"{length:x}-{secs:x}\"")
+        });
+        let last_modified: Option<String> = modified.map(date::http_date);
+        /*- Tells clients that resuming downloads using ranges works -*/
+        let mut headers: Vec<String> = vec![String::from("Accept-Ranges: bytes")];
+        if let (Some(etag), Some(last_modified)) = (&etag, &last_modified) {
+            headers.push(format!("ETag: {etag}"));
+            headers.push(format!("Last-Modified: {last_modified}"));
+        };
 
-        /*- Find if exists in file cache -*/
-        if let Ok(fc) = FILE_CACHE.lock() {
-            match fc.get(
-                &_path
-                    .canonicalize()
-                    .unwrap_or(PathBuf::from(""))
-                    .display()
-                    .to_string(),
-            ) {
-                Some(buf) => {
-                    return self.respond(
-                        status,
-                        Respond::new()
-                            .content(&String::from_utf8_lossy(&buf), ResponseType::guess(_path)),
-                    )
+        /*- Ranges are ignored if If-Range doesn't match the current file -*/
+        let range = match self.header("Range") {
+            Some(range) if status == 200 => headers::parse_range(range, length),
+            _ => None,
+        };
+        let range = match self.header("If-Range") {
+            Some(if_range) => {
+                let if_range: &str = if_range.trim();
+                if etag.as_deref() == Some(if_range) || last_modified.as_deref() == Some(if_range) {
+                    range
+                } else {
+                    None
                 }
-                None => (),
             }
+            None => range,
         };
 
-        /*- Open file -*/
-        let content: String = match File::open(_path) {
-            Ok(mut e) => {
-                let mut content: String = String::new();
-                if e.read_to_string(&mut content).is_ok() {}
-
-                content
+        match range {
+            Some(Ok((start, end))) => {
+                if reader.seek(SeekFrom::Start(start)).is_err() {
+                    return self.respond_status(500u16);
+                };
+                headers.push(format!("Content-Range: bytes {start}-{end}/{length}"));
+                self.respond_reader(
+                    206u16,
+                    response_type,
+                    end - start + 1,
+                    reader,
+                    Some(headers),
+                );
             }
-            Err(_) => String::new(),
+            Some(Err(_)) => {
+                headers.push(format!("Content-Range: bytes */{length}"));
+                self.respond(416u16, Respond::new().text("").headers(headers));
+            }
+            None => self.respond_reader(status, response_type, length, reader, Some(headers)),
+        }
+    }
+
+    /*- Respond with a file from the file cache. Returns false if it isn't cached -*/
+    pub(crate) fn respond_cached(&mut self, status: u16, path: &Path) -> bool {
+        #[cfg(feature = "gzip")]
+        if let Some(gzipped) = cached_gzip_file(path) {
+            self.respond_gzipped(status, path, gzipped);
+            return true;
         };
 
-        /*- Return -*/
-        self.respond(
-            status,
-            Respond::new().content(&content, ResponseType::guess(_path)),
-        )
+        match cached_file(path) {
+            Some(buf) => {
+                let mut file: Cursor<Vec<u8>> = Cursor::new(buf);
+                let response_type: ResponseType = self.file_type(path, &mut file);
+                let length: u64 = file.get_ref().len() as u64;
+                self.respond_file_reader(status, response_type, length, None, file);
+                true
+            }
+            None => false,
+        }
     }
 
-    /*- Get cookies -*/
-    /// ## Example
-    /// ```
-    /// use responder::prelude::*;
-    /// use std::collections::HashMap;
-    ///
-    /// fn endpoint(stream:&mut Stream) -> () {
-    ///     let cookies:HashMap<&str, &str> = stream.get_cookies();
-    ///
-    ///     /* Try get some cookie */
-    ///     let token = cookies.get("token").unwrap();
-    /// }
-    /// ```
-    pub fn get_cookies(&self) -> HashMap<&str, &str> {
-        let mut cookies: HashMap<&str, &str> = HashMap::new();
+    /*- Respond with a gzip compressed file, which is sent as it is to clients
+    accepting gzip. Ranges are only supported for the decompressed file -*/
+    #[cfg(feature = "gzip")]
+    fn respond_gzipped(&mut self, status: u16, path: &Path, gzipped: Vec<u8>) {
+        self.add_vary("Accept-Encoding");
+        let start: Vec<u8> = encoding::gunzip(&gzipped, 16).unwrap_or_default();
+        let response_type: ResponseType = self.file_type(path, &mut Cursor::new(start));
 
-        if let Some(cookie) = self.headers.get("Cookie") {
-            for cookie in cookie.split("; ") {
-                let cookie: Vec<&str> = cookie.split("=").collect();
-                cookies.insert(
-                    match cookie.get(0) {
-                        Some(e) => e,
-                        None => continue,
-                    },
-                    match cookie.get(1) {
-                        Some(e) => e,
-                        None => continue,
-                    },
-                );
-            }
+        if self
+            .header("Accept-Encoding")
+            .is_some_and(|accept_encoding| headers::accepts(accept_encoding, "gzip"))
+        {
+            let length: u64 = gzipped.len() as u64;
+            return self.respond_reader(
+                status,
+                response_type,
+                length,
+                Cursor::new(gzipped),
+                Some(vec![String::from("Content-Encoding: gzip")]),
+            );
         };
 
-        cookies
+        match encoding::gunzip(&gzipped, usize::MAX) {
+            Ok(buf) => {
+                let length: u64 = buf.len() as u64;
+                self.respond_file_reader(status, response_type, length, None, Cursor::new(buf));
+            }
+            Err(_) => self.respond_status(500u16),
+        };
     }
 
-    /*- Getters -*/
-    /// Aquire a reference to the body string
-    pub fn body(&self) -> &String {
-        &self.body
+    /*- Pump a reader to the client in chunks. Stops as soon as
+    a write fails, (like when the client has hung up) -*/
+    fn pump(&mut self, mut reader: impl Read) -> std::io::Result<u64> {
+        let mut chunk = [0u8; 8192];
+        let mut written: u64 = 0;
+
+        loop {
+            let read = match reader.read(&mut chunk) {
+                Ok(0) => return Ok(written),
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            self.capture(&chunk[..read]);
+            if !self.write_inner(&chunk[..read]) {
+                return Err(ErrorKind::BrokenPipe.into());
+            };
+            written += read as u64;
+        }
+    }
+
+    /*- Write the head and body of a response. Responses to HEAD
+    requests only get the head, (but with the same headers) -*/
+    fn write_response(&mut self, head: String, body: &[u8]) {
+        if self.write_inner(head.as_bytes()) && self.method != Method::HEAD {
+            self.capture(body);
+            self.write_inner(body);
+        };
+    }
+
+    /*- Keep the start of the response body, if the audit log asks for it -*/
+    fn capture(&mut self, body: &[u8]) {
+        let left: usize = self.capture_limit - self.captured_response.len();
+        self.captured_response
+            .extend_from_slice(&body[..body.len().min(left)]);
+    }
+
+    /*- Read from the inner stream -*/
+    pub(crate) fn read_inner(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.stream_inner.get_mut().read(buffer)
+    }
+
+    /*- Write all bytes to the inner stream. Writes are buffered, so small
+    pieces (like heads and chunks) are batched until the buffer is full or
+    flushed. A single `write` call might only write parts of the buffer
+    (to slow clients), so `write_all` is used -*/
+    fn write_inner(&mut self, bytes: &[u8]) -> bool {
+        if self.disconnected {
+            return false;
+        };
+        match self.stream_inner.write_all(bytes) {
+            Ok(_) => true,
+            Err(e) => {
+                self.write_failed(e);
+                false
+            }
+        }
+    }
+
+    /*- Flush the buffered writes to the inner stream, unless writing
+    to it has already failed. Called at the end of every response -*/
+    fn flush_inner(&mut self) {
+        if self.disconnected {
+            return;
+        };
+        if let Err(e) = self.stream_inner.flush() {
+            self.write_failed(e);
+        };
+    }
+
+    /*- Writing to the client failed, most likely because they disconnected -*/
+    fn write_failed(&mut self, error: std::io::Error) {
+        self.disconnected = true;
+        if !self.logs {
+            return;
+        };
+
+        /*- Write timeouts show up as WouldBlock on unix, and TimedOut on windows -*/
+        match error.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+                eprintln!("[responder] Timed out writing response, dropping connection")
+            }
+            _ => eprintln!("[responder] Failed writing response: {error}"),
+        };
+    }
+
+    /*- Construct the status line and headers of a response with content -*/
+    fn content_head(
+        &self,
+        status: u16,
+        response_type: ResponseType,
+        length: u64,
+        additional_headers: Option<Vec<String>>,
+    ) -> String {
+        let mut headers: Vec<String> = vec![
+            format!("Content-Length: {}", length),
+            format!("Content-Type: {}", response_type.content_type()),
+        ];
+        headers.extend(additional_headers.unwrap_or_default());
+
+        self.head(status, headers)
+    }
+
+    /*- Construct the status line and headers of a response. The server's default
+    headers are appended, unless a header with the same name was provided -*/
+    fn head(&self, status: u16, mut headers: Vec<String>) -> String {
+        let has_header = |headers: &[String], name: &str| {
+            headers.iter().any(|header| {
+                header
+                    .split_once(':')
+                    .is_some_and(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            })
+        };
+        if !self.vary.is_empty() && !has_header(&headers, "vary") {
+            headers.push(format!("Vary: {}", self.vary.join(", ")));
+        };
+
+        /*- Tell the client if the connection will be reused after the response -*/
+        if status >= 200 && !has_header(&headers, "connection") {
+            headers.push(String::from(match self.keep_alive {
+                true => "Connection: keep-alive",
+                false => "Connection: close",
+            }));
+        };
+
+        /*- Status codes missing from the table get an empty reason phrase -*/
+        let mut head: String = match &self.reason {
+            Some(reason) => format!("HTTP/1.1 {} {}", status, reason),
+            None => match reason_phrase(status) {
+                Some(reason) => format!("HTTP/1.1 {} {}", status, reason),
+                None => format!("HTTP/1.1 {} ", status),
+            },
+        };
+
+        /*- Line breaks in headers, (like from user input being put into a
+        header) would let the rest of the value become new headers, or even
+        a whole new response, so they're removed -*/
+        for header in headers.iter() {
+            head.push_str("\r\n");
+            self.push_header(&mut head, header);
+        }
+
+        for (key, value) in self.default_headers.iter() {
+            let overridden = headers.iter().any(|header| match header.split_once(':') {
+                Some((name, _)) => name.trim().eq_ignore_ascii_case(key),
+                None => false,
+            });
+            if !overridden {
+                head.push_str("\r\n");
+                self.push_header(&mut head, &format!("{key}: {value}"));
+            };
+        }
+
+        head.push_str(&self.cors_headers());
+        head.push_str("\r\n\r\n");
+        head
+    }
+
+    /*- Write a header without line breaks, with its name in
+    Title-Case if the server canonicalizes header names -*/
+    fn push_header(&self, head: &mut String, header: &str) {
+        let header: String = header
+            .chars()
+            .filter(|c| !matches!(c, '\r' | '\n'))
+            .collect();
+        match header.split_once(':') {
+            Some((name, value)) if self.canonicalize_headers => {
+                head.push_str(&headers::canonicalize(name));
+                head.push(':');
+                head.push_str(value);
+            }
+            _ => head.push_str(&header),
+        };
+    }
+
+    /// Respond with just status code
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /* Repond with 200 (OK) */
+    /// fn endpoint_200(stream:&mut Stream) -> () {
+    ///     stream.respond_status(200u16); // Response body will look like this: "200 OK"
+    /// }
+    ///
+    /// /* Repond with 600 (status code doesn't exist) */
+    /// fn endpoint_600(stream:&mut Stream) -> () {
+    ///     stream.respond_status(600u16); // Response body will look like this: "600 Internal error - Missing status code"
+    /// }
+    /// ```
+    pub fn respond_status(&mut self, status: u16) {
+        self.respond(status, Respond::new());
+    }
+
+    /// Send back an http-response with a custom reason phrase, instead
+    /// of the one from the built-in status code table. Can also be
+    /// used to provide a reason phrase for status codes not in the table.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /* Status line will look like this: "HTTP/1.1 422 Validation Failed" */
+    /// fn endpoint(stream: &mut Stream) {
+    ///     stream.respond_with_reason(422u16, "Validation Failed", Respond::new().text("Missing name"));
+    /// }
+    /// ```
+    pub fn respond_with_reason(&mut self, status: u16, reason: &str, respond: Respond) {
+        if self.buf_written_to {
+            return;
+        };
+
+        /*- Line breaks would end the status line early -*/
+        self.reason = Some(reason.replace(['\r', '\n'], " "));
+        self.respond(status, respond);
+    }
+
+    /*- Get the reason phrase of a status code -*/
+    fn status_msg(&self, status: u16) -> &str {
+        match &self.reason {
+            Some(reason) => reason,
+            None => reason_phrase(status).unwrap_or("Internal error - Missing status code"),
+        }
+    }
+
+    /// Respond with JSON payload, takes `payload` as param, which can be any type that can be converted into a `String`
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /* Repond with some JSON values (TIP: use the `json!()` macro from the `serde_json` crate) */
+    /// fn endpoint_payload(stream:&mut Stream) -> () {
+    ///     stream.payload("{{\"key\": \"value\"}}");
+    /// }
+    /// ```
+    pub fn payload(&mut self, payload: impl ToString) {
+        self.respond(200, Respond::new().json(&payload.to_string()));
+    }
+
+    /// Respond with a serializable value as JSON, with the http-status 200.
+    /// Responds with 500 if the value can't be serialized.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream:&mut Stream) {
+    ///     stream.json_ok(json!({ "status": "ok" }));
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn json_ok(&mut self, value: impl serde::Serialize) {
+        match serde_json::to_string(&value) {
+            Ok(json) => self.respond(200u16, Respond::new().json(&json)),
+            Err(_) => self.respond_status(500u16),
+        }
+    }
+
+    /// Respond with a JSON array whose elements are serialized as they're
+    /// yielded, (using chunked transfer encoding) so big collections like
+    /// database rows never have to be in memory at once. If an element
+    /// can't be serialized, the connection is closed, as the status has
+    /// already been sent.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream:&mut Stream) {
+    ///     let rows = (0..10_000).map(|id| json!({ "id": id, "name": format!("user {id}") }));
+    ///     stream.json_array_stream(200u16, rows);
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn json_array_stream<T: serde::Serialize>(
+        &mut self,
+        status: u16,
+        items: impl IntoIterator<Item = T>,
+    ) {
+        let elements = items.into_iter().enumerate().map(|(index, item)| {
+            let mut chunk: Vec<u8> = match index {
+                0 => Vec::new(),
+                _ => vec![b','],
+            };
+            serde_json::to_writer(&mut chunk, &item)?;
+            Ok(chunk)
+        });
+        let chunks = std::iter::once(Ok(vec![b'[']))
+            .chain(elements)
+            .chain(std::iter::once(Ok(vec![b']'])));
+
+        self.respond_chunked(status, ResponseType::Json, chunks, &[], Vec::new);
+    }
+
+    /// Respond with a JSON object containing the http-status, (ex
+    /// `{"status":404,"error":"not found"}`) so the http-status and the
+    /// body agree. `extra` has to serialize into an object, whose fields
+    /// are added next to the status, (or `()` for just the status).
+    /// Responds with 500 if it can't be serialized.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream:&mut Stream) {
+    ///     stream.respond_json_status(404u16, json!({ "error": "user not found" }));
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn respond_json_status(&mut self, status: u16, extra: impl serde::Serialize) {
+        let mut object = match serde_json::to_value(&extra) {
+            Ok(serde_json::Value::Object(object)) => object,
+            Ok(serde_json::Value::Null) => serde_json::Map::new(),
+            _ => return self.respond_status(500u16),
+        };
+        object.insert(String::from("status"), status.into());
+
+        let json: String = serde_json::Value::Object(object).to_string();
+        self.respond(status, Respond::new().json(&json));
+    }
+
+    /// Respond with JSON payload status
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /* Repond with payload status (real http status will be 200) */
+    /// fn endpoint(stream:&mut Stream) -> () {
+    ///     stream.payload_status(200);
+    /// }
+    /// ```
+    ///
+    /// ## Usecases
+    /// In JavaScript, you often want payload status responses instead of http-status responses, for example
+    /// you have a backend API with diffrent user-profiles. And on the client-side some user searches for a
+    /// non-existing user. We'd like to respond with a payload status of 404, not http-status 404 because that
+    /// would make JavaScript think that the route specified in the fetch request is invalid, eventhough it's
+    /// valid - only that the user wasn't found.
+    pub fn payload_status(&mut self, status: u16) {
+        self.payload(format!("{{\"status\":{}}}", status));
+    }
+
+    /// Respond with a JSON payload status and a human-readable message,
+    /// like `payload_status` (the real http status will be 200)
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /* Body will look like this: {"status":404,"message":"User not found"} */
+    /// fn endpoint(stream:&mut Stream) -> () {
+    ///     stream.payload_status_msg(404, "User not found");
+    /// }
+    /// ```
+    pub fn payload_status_msg(&mut self, status: u16, message: &str) {
+        self.payload(format!(
+            "{{\"status\":{},\"message\":\"{}\"}}",
+            status,
+            json::escape(message)
+        ));
+    }
+
+    /// Close the connection without responding, like when abuse has been
+    /// detected. Nothing more can be written to the stream afterwards.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream: &mut Stream) {
+    ///     if stream.header("User-Agent").is_none() {
+    ///         return stream.close();
+    ///     };
+    /// }
+    /// ```
+    pub fn close(&mut self) {
+        self.buf_written_to = true;
+        self.keep_alive = false;
+        self.stream_inner.get_ref().shutdown(Shutdown::Both).ok();
+    }
+
+    /// Get a mutable reference of the inner stream because
+    /// the stream_inner key isn't exposed publicly. Gives
+    /// access to more in-depth functionality. The connection
+    /// won't be reused for more requests after this, because
+    /// the response might have been written directly.
+    ///
+    /// ## Examples
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream:&mut Stream) -> () {
+    ///     stream.get_mut_inner_ref(); // -> &mut TcpStream
+    /// }
+    /// ```
+    pub fn get_mut_inner_ref(&mut self) -> &mut TcpStream {
+        self.keep_alive = false;
+        self.flush_inner();
+        self.stream_inner.get_mut()
+    }
+
+    /// Redirect requests to url, might not work with all browsers so
+    /// a link will appear which users can click incase it doesn't work.
+    /// The html can be customized using `Server::redirect_body_template`.
+    ///
+    /// ## Examples
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn redirect_user(stream:&mut Stream) -> () {
+    ///     stream.redirect("https://google.com");
+    /// }
+    /// ```
+    pub fn redirect(&mut self, url: &str) {
+        let body: String = match self.redirect_body {
+            Some(template) => template(url),
+            None => format!(
+                "<html><head><meta http-equiv=\"refresh\" content=\"0; url={}\" /></head><body><a href=\"{}\">Click here if you are not redirected</a></body></html>",
+                url,
+                url
+            ),
+        };
+
+        self.redirect_with(url, &body);
+    }
+
+    /// Redirect requests to url, with a custom html body which is shown to
+    /// clients that don't follow the redirect. An empty body won't be sent.
+    ///
+    /// ## Examples
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn redirect_user(stream:&mut Stream) {
+    ///     stream.redirect_with("/sv/start", "<a href=\"/sv/start\">Klicka här</a>");
+    /// }
+    /// ```
+    pub fn redirect_with(&mut self, url: &str, body: &str) {
+        self.respond(
+            308u16,
+            Respond::new()
+                .content(body, ResponseType::Html)
+                .headers(vec![format!("Location: {}", url)]),
+        );
+    }
+
+    /*- Append request data (body, headers, url-params) to self -*/
+    /// Builder pattern for making the `Stream` struct. Not meant to be used
+    pub fn set_body(&mut self, body: String) -> &mut Self {
+        self.body = body;
+        self
+    }
+    /*- The raw body bytes, used for decoding other charsets than utf-8 -*/
+    pub(crate) fn set_raw_body(&mut self, raw_body: Vec<u8>) -> &mut Self {
+        self.raw_body = raw_body;
+        self
+    }
+    /*- The response depends on a request header, (like when negotiating
+    the content type) so caches must not reuse it for other values -*/
+    pub(crate) fn add_vary(&mut self, header: &'static str) -> &mut Self {
+        if !self.vary.contains(&header) {
+            self.vary.push(header);
+        };
+        self
+    }
+    pub(crate) fn set_route_template(&mut self, route_template: String) -> &mut Self {
+        self.route_template = Some(route_template);
+        self
+    }
+    pub(crate) fn set_trailers(&mut self, trailers: Vec<(String, String)>) -> &mut Self {
+        self.trailers = trailers;
+        self
+    }
+    /*- Leave the body unread, for handlers to stream. `buffered`
+    is what has been recieved of it along with the headers -*/
+    pub(crate) fn set_unread_body(
+        &mut self,
+        buffered: Vec<u8>,
+        framing: Framing,
+        max: Option<usize>,
+    ) -> &mut Self {
+        self.unread_body = Some(UnreadBody {
+            buffered,
+            pos: 0,
+            framing,
+            read: 0,
+            max,
+        });
+        self
+    }
+    /*- Set the maximum body size for the matched route. Returns false if the
+    body is bigger, (as far as is known before a streamed body has been read) -*/
+    pub(crate) fn limit_body(&mut self, max: Option<usize>) -> bool {
+        match &mut self.unread_body {
+            Some(body) => {
+                body.max = max;
+                let size: u64 = match body.framing {
+                    Framing::Length(left) => (body.read as u64).saturating_add(left),
+                    _ => body.read as u64,
+                };
+                max.is_none_or(|max| size <= max as u64)
+            }
+            None => max.is_none_or(|max| self.raw_body.len() <= max),
+        }
+    }
+    /*- After the handler, move bytes recieved after the body to `pending`. Returns
+    false if the body wasn't read completely, (the connection can't be reused then) -*/
+    pub(crate) fn finish_body(&mut self, pending: &mut Vec<u8>) -> bool {
+        match self.unread_body.take() {
+            Some(UnreadBody {
+                framing: Framing::Done,
+                buffered,
+                pos,
+                ..
+            }) => {
+                pending.extend_from_slice(&buffered[pos..]);
+                true
+            }
+            Some(_) => false,
+            None => true,
+        }
+    }
+    /// Builder pattern for making the `Stream` struct. Not meant to be used
+    pub fn set_headers(&mut self, headers: HashMap<&'a str, &'a str>) -> &mut Self {
+        self.headers = headers.iter().map(|(k, v)| (*k, *v)).collect();
+        self.headers_map = OnceCell::from(headers);
+        self
+    }
+    /*- Set the headers as they were parsed from the request -*/
+    pub(crate) fn set_header_list(&mut self, headers: Vec<(&'a str, &'a str)>) {
+        self.headers = headers;
+        self.headers_map = OnceCell::new();
+    }
+    /*- The headers in the order they were recieved, including duplicates -*/
+    pub(crate) fn header_list(&self) -> &[(&'a str, &'a str)] {
+        &self.headers
+    }
+    /// Builder pattern for making the `Stream` struct. Not meant to be used
+    pub fn set_info(&mut self, info: RequestInfo<'a>) -> &mut Self {
+        self.method = info.method;
+        self.path = info.path;
+        self.query = info.query;
+        self
+    }
+    /// Builder pattern for making the `Stream` struct. Not meant to be used
+    pub fn set_params(&mut self, params: HashMap<String, String>) -> &mut Self {
+        self.params = params;
+        self
+    }
+
+    /// Require headers to be specified. If they are not, this
+    /// function will repsond with an array containing missing
+    /// headers. Return true indicating that the request should
+    /// be cancelled or not.
+    ///
+    /// ## Examples
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /*- Return if headers were not specified -*/
+    /// fn endpoint(stream:&mut Stream) -> () {
+    ///     if stream.expect_headers(&["authentification"]) { return; };
+    ///
+    ///     /* Handle authorized requests... */
+    /// }
+    /// ```
+    pub fn expect_headers(&mut self, headers: &[&str]) -> bool {
+        for expected_header in headers {
+            if !self.headers.iter().any(|(key, _)| key == expected_header) {
+                self.respond(
+                    400u16,
+                    Respond::new().text(&format!(
+                        "This endpoint requires these headers: {headers:?}"
+                    )),
+                );
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Require headers to be specified, but ignore encapsulation. If
+    /// headers are not set, this function will repsond with an array
+    /// containing missing headers. Return true indicating that the
+    /// request should be cancelled or not.
+    ///
+    /// ## Examples
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /*- Return if headers were not specified -*/
+    /// fn endpoint(stream:&mut Stream) -> () {
+    ///     if stream.expect_headers(&["authentification"]) { return; };
+    ///
+    ///     /* Handle authorized requests... */
+    /// }
+    /// ```
+    pub fn expect_headers_ignore_caps(&mut self, headers: &[&str]) -> bool {
+        for expected_header in headers {
+            if self.header(expected_header).is_none() {
+                self.respond(
+                    400u16,
+                    Respond::new().text(&format!(
+                        "This endpoint requires these headers: {headers:?}"
+                    )),
+                );
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Require the request body to be of a specific content type, like
+    /// `application/json`. If it isn't, this function will respond with
+    /// `415 Unsupported Media Type`. Parameters like `; charset=utf-8` are
+    /// ignored. Return true indicating that the request should be cancelled.
+    ///
+    /// ## Examples
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /*- Return if the body isn't json -*/
+    /// fn endpoint(stream:&mut Stream) {
+    ///     if stream.expect_content_type("application/json") { return; };
+    ///
+    ///     /* Handle json requests... */
+    /// }
+    /// ```
+    pub fn expect_content_type(&mut self, content_type: &str) -> bool {
+        let matches = match self.header("Content-Type") {
+            Some(value) => value
+                .split(';')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .eq_ignore_ascii_case(content_type),
+            None => false,
+        };
+
+        if !matches {
+            self.respond(
+                415u16,
+                Respond::new().text(&format!(
+                    "This endpoint requires the content type: {content_type:?}"
+                )),
+            );
+            return true;
+        }
+
+        false
+    }
+
+    /*- Respond with file -*/
+    /// Files are sent with `ETag` and `Last-Modified` headers, and
+    /// single byte ranges (`Range` / `If-Range`) are supported for
+    /// resumable downloads, (advertised using `Accept-Ranges: bytes`).
+    /// If the file can't be opened, the response is `404 Not Found`,
+    /// (or `403 Forbidden` / `500 Internal Server Error`) instead.
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream:&mut Stream) -> () {
+    ///     stream.respond_file(200u16, "/path/to/file.png")
+    /// }
+    /// ```
+    pub fn respond_file(&mut self, status: u16, path: &str) {
+        /*- Grab the path -*/
+        let _path = Path::new(path);
+
+        /*- Find if exists in file cache -*/
+        if self.respond_cached(status, _path) {
+            return;
+        };
+
+        /*- Open file, and stream it to the client -*/
+        let (mut file, metadata) = match File::open(_path).and_then(|file| {
+            let metadata = file.metadata()?;
+            Ok((file, metadata))
+        }) {
+            Ok(e) => e,
+            /*- Missing files shouldn't look like successful, empty responses -*/
+            Err(e) => {
+                return self.respond_status(match e.kind() {
+                    ErrorKind::NotFound => 404u16,
+                    ErrorKind::PermissionDenied => 403u16,
+                    _ => 500u16,
+                })
+            }
+        };
+
+        /*- Directories can be opened, but not read -*/
+        if metadata.is_dir() {
+            return self.respond_status(404u16);
+        };
+        let modified: Option<SystemTime> = metadata.modified().ok();
+        let response_type: ResponseType = self.file_type(_path, &mut file);
+        self.respond_file_reader(status, response_type, metadata.len(), modified, file)
+    }
+
+    /// Respond with an html template, where `{{key}}` placeholders are
+    /// replaced by html escaped values from the context, and `{{{key}}}`
+    /// placeholders by raw values. Placeholders which aren't in the context
+    /// render empty, unless the server sets `missing_template_keys`.
+    /// Templates are read from the file cache if they're cached.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::collections::HashMap;
+    ///
+    /// /* <h1>Hello, {{name}}!</h1> */
+    /// fn endpoint(stream:&mut Stream) -> () {
+    ///     let mut context: HashMap<String, String> = HashMap::new();
+    ///     context.insert("name".into(), "<John>".into());
+    ///
+    ///     /* Renders: <h1>Hello, &lt;John&gt;!</h1> */
+    ///     stream.respond_template(200u16, "./static/hello.html", &context);
+    /// }
+    /// ```
+    pub fn respond_template(
+        &mut self,
+        status: u16,
+        template_path: &str,
+        context: &HashMap<String, String>,
+    ) {
+        let path: &Path = Path::new(template_path);
+        let template: Vec<u8> = match cached_file(path) {
+            Some(buf) => buf,
+            None => match Self::read_template(path) {
+                Ok(buf) => buf,
+                Err(e) => {
+                    return self.respond_status(match e.kind() {
+                        ErrorKind::NotFound => 404u16,
+                        ErrorKind::PermissionDenied => 403u16,
+                        _ => 500u16,
+                    })
+                }
+            },
+        };
+        let template: &str = match std::str::from_utf8(&template) {
+            Ok(e) => e,
+            Err(_) => return self.respond_status(500u16),
+        };
+
+        match html::render(template, context, self.missing_keys == MissingKeys::Empty) {
+            Ok(rendered) => self.respond(status, Respond::new().html(&rendered)),
+            Err(key) => {
+                if self.logs {
+                    eprintln!("[responder] Template {template_path} is missing the key {key}");
+                };
+                self.respond_status(500u16)
+            }
+        };
+    }
+
+    /*- Render the error page template for an error status, if one is set and
+    can be read. Status responses without a template or body are plain text -*/
+    fn error_page(&self, status: u16) -> Option<String> {
+        let path: &Path = Path::new(self.error_page_template.filter(|_| status >= 400)?);
+        let template: Vec<u8> = match cached_file(path) {
+            Some(buf) => buf,
+            None => Self::read_template(path).ok()?,
+        };
+        let context: HashMap<String, String> = HashMap::from([
+            (String::from("status"), status.to_string()),
+            (String::from("reason"), self.status_msg(status).to_string()),
+            (String::from("path"), self.path.to_string()),
+        ]);
+
+        html::render(std::str::from_utf8(&template).ok()?, &context, true).ok()
+    }
+
+    /*- Read a template which isn't in the (uncompressed) file cache -*/
+    fn read_template(path: &Path) -> std::io::Result<Vec<u8>> {
+        #[cfg(feature = "gzip")]
+        if let Some(gzipped) = cached_gzip_file(path) {
+            return encoding::gunzip(&gzipped, usize::MAX);
+        };
+        std::fs::read(path)
+    }
+
+    /*- Get cookies -*/
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::collections::HashMap;
+    ///
+    /// fn endpoint(stream:&mut Stream) -> () {
+    ///     let cookies:HashMap<&str, &str> = stream.get_cookies();
+    ///
+    ///     /* Try get some cookie */
+    ///     let token = cookies.get("token").unwrap();
+    /// }
+    /// ```
+    pub fn get_cookies(&self) -> HashMap<&str, &str> {
+        self.cookie_pairs().collect()
+    }
+
+    /// Get the value of a single cookie from the `Cookie` header.
+    /// Quotes around the value are removed
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream:&mut Stream) {
+    ///     let token: &str = match stream.cookie("token") {
+    ///         Some(e) => e,
+    ///         None => return stream.respond_status(401u16),
+    ///     };
+    /// }
+    /// ```
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookie_pairs()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /*- Parse the Cookie header into names and values, (ex `a=1; b="2"`).
+    Values can contain `=`, and whitespace around `;` is ignored -*/
+    fn cookie_pairs(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.header("Cookie")
+            .unwrap_or("")
+            .split(';')
+            .filter_map(|cookie| cookie.split_once('='))
+            .map(|(key, value)| {
+                let value: &str = value.trim();
+                let value: &str = match value.strip_prefix('"').and_then(|e| e.strip_suffix('"')) {
+                    Some(unquoted) => unquoted,
+                    None => value,
+                };
+                (key.trim(), value)
+            })
+    }
+
+    /*- Forward the request to another server -*/
+    /// Forward the current request (method, headers and body) to an upstream
+    /// http-server, and pipe the upstream response back to the client. The
+    /// request path is appended to the path of `url`. Streamed bodies (see
+    /// `BodyMode::Streaming`) are forwarded as they're read from the connection.
+    /// Hop-by-hop headers, (like `Connection`, `Keep-Alive` and `Upgrade`) only
+    /// apply to the connection to the client, so they aren't forwarded.
+    /// Responds with `502` if the upstream server can't be reached, and with
+    /// `501` if the request method is unknown.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /* GET /api/users -> http://127.0.0.1:3000/api/users */
+    /// fn endpoint(stream:&mut Stream) {
+    ///     stream.proxy_to("http://127.0.0.1:3000").ok();
+    /// }
+    /// ```
+    #[cfg(feature = "proxy")]
+    pub fn proxy_to(&mut self, url: &str) -> Result<u64, ProxyError> {
+        /*- Check buffer write access -*/
+        if self.buf_written_to {
+            return Err(ProxyError::AlreadyResponded);
+        };
+
+        /*- Methods we couldn't parse can't be written upstream either -*/
+        if self.method == Method::UNKNOWN {
+            self.respond_status(501u16);
+            return Err(ProxyError::UnknownMethod);
+        };
+
+        /*- Split url into host and path prefix -*/
+        let url = match url.strip_prefix("http://") {
+            Some(e) => e,
+            None => return Err(ProxyError::InvalidUrl),
+        };
+        let (host, prefix) = match url.split_once('/') {
+            Some((host, prefix)) => (host, prefix.trim_end_matches('/')),
+            None => (url, ""),
+        };
+        if host.is_empty() {
+            return Err(ProxyError::InvalidUrl);
+        };
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:80")
+        };
+
+        /*- Connect to upstream -*/
+        let mut upstream = match TcpStream::connect(address) {
+            Ok(e) => e,
+            Err(e) => {
+                self.respond_status(502u16);
+                return Err(ProxyError::Io(e));
+            }
+        };
+
+        /*- Reconstruct the request -*/
+        let path = self.path.trim_start_matches('/');
+        let mut target = if prefix.is_empty() {
+            format!("/{path}")
+        } else {
+            format!("/{prefix}/{path}")
+        };
+        if !self.query.is_empty() {
+            target.push('?');
+            target.push_str(self.query);
+        };
+        let mut request = format!(
+            "{} {target} HTTP/1.1\r\nHost: {host}\r\n",
+            self.method.as_str()
+        );
+
+        /*- Headers named in the Connection header are hop-by-hop too. Buffered
+        bodies have been decoded already, (with the gzip feature) so they
+        aren't sent with their Content-Encoding -*/
+        let connection: Vec<&str> = self
+            .headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("connection"))
+            .flat_map(|(_, value)| value.split(',').map(str::trim))
+            .collect();
+        let decoded: bool = cfg!(feature = "gzip") && self.unread_body.is_none();
+        for (key, value) in self.headers.iter() {
+            if PROXY_SKIPPED_HEADERS
+                .iter()
+                .chain(connection.iter())
+                .any(|skipped| skipped.eq_ignore_ascii_case(key))
+                || (decoded && key.eq_ignore_ascii_case("content-encoding"))
+            {
+                continue;
+            };
+            request.push_str(&format!("{key}: {value}\r\n"));
+        }
+
+        /*- Streamed bodies keep the framing the client sent them with -*/
+        let chunked: bool = match &self.unread_body {
+            Some(UnreadBody {
+                framing: Framing::Length(left),
+                ..
+            }) => {
+                request.push_str(&format!("Content-Length: {left}\r\n"));
+                false
+            }
+            Some(UnreadBody {
+                framing: Framing::Chunked(..),
+                ..
+            }) => {
+                request.push_str("Transfer-Encoding: chunked\r\n");
+                true
+            }
+            Some(_) => false,
+            None => {
+                if !self.raw_body.is_empty() {
+                    request.push_str(&format!("Content-Length: {}\r\n", self.raw_body.len()));
+                };
+                false
+            }
+        };
+        request.push_str("Connection: close\r\n\r\n");
+
+        /*- Write the request upstream -*/
+        if let Err(e) = upstream
+            .write_all(request.as_bytes())
+            .and_then(|_| self.forward_body(&mut upstream, chunked))
+        {
+            self.respond_status(502u16);
+            return Err(ProxyError::Io(e));
+        };
+
+        /*- Pipe the response back to the client. The upstream response
+        ends when the upstream closes, so this connection has to as well -*/
+        self.buf_written_to = true;
+        self.keep_alive = false;
+        let written = self.pump(upstream).map_err(ProxyError::Io)?;
+        self.flush_inner();
+
+        Ok(written)
+    }
+
+    /*- Copy the request body to the upstream server of `proxy_to`,
+    re-chunking it if the client sent it chunked -*/
+    #[cfg(feature = "proxy")]
+    fn forward_body(&mut self, upstream: &mut TcpStream, chunked: bool) -> std::io::Result<()> {
+        if !chunked {
+            return std::io::copy(&mut self.body_reader(), upstream).map(|_| ());
+        };
+
+        let mut chunk = [0u8; 8192];
+        let mut reader = self.body_reader();
+        loop {
+            let read: usize = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            upstream.write_all(format!("{read:x}\r\n").as_bytes())?;
+            upstream.write_all(&chunk[..read])?;
+            upstream.write_all(b"\r\n")?;
+        }
+        upstream.write_all(b"0\r\n\r\n")
+    }
+
+    /*- Parse the body as json, with a size limit -*/
+    /// Parse the request body as JSON, but refuse to parse bodies bigger
+    /// than `max_bytes`. This limit is separate from the server-wide
+    /// `max_body_size`, so JSON endpoints can have tighter limits than
+    /// endpoints recieving files.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::collections::HashMap;
+    ///
+    /// fn endpoint(stream:&mut Stream) {
+    ///     let user: HashMap<String, String> = match stream.json_limited(1024 * 1024) {
+    ///         Ok(e) => e,
+    ///         Err(e) => return stream.respond_status(e.status()),
+    ///     };
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn json_limited<T: serde::de::DeserializeOwned>(
+        &self,
+        max_bytes: usize,
+    ) -> Result<T, JsonError> {
+        if self.raw_body.len() > max_bytes {
+            return Err(JsonError::TooLarge);
+        };
+
+        serde_json::from_slice(&self.raw_body).map_err(JsonError::Invalid)
+    }
+
+    /*- Merge the body into a json value -*/
+    /// Parse the request body as JSON and merge it into `target` using
+    /// JSON Merge Patch semantics (see [`crate::merge_json`]). Useful
+    /// for PATCH endpoints which recieve partial objects.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use serde_json::json;
+    ///
+    /// fn endpoint(stream:&mut Stream) {
+    ///     let mut user = json!({ "name": "John", "age": 30 });
+    ///     if stream.json_patch(&mut user).is_err() {
+    ///         return stream.respond_status(400u16);
+    ///     };
+    ///
+    ///     stream.payload(user);
+    /// }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn json_patch(&self, target: &mut serde_json::Value) -> Result<(), serde_json::Error> {
+        let patch: serde_json::Value = serde_json::from_slice(&self.raw_body)?;
+        crate::merge_json(target, &patch);
+        Ok(())
+    }
+
+    /*- Getters -*/
+    /// Aquire a reference to the body string
+    pub fn body(&self) -> &String {
+        &self.body
+    }
+
+    /// The request body as the raw bytes which were recieved. It is
+    /// read before any routing happens, so `ControlledStack` gates can
+    /// inspect it (like verifying a webhook signature) and the handler
+    /// still gets the full body afterwards.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// # fn hmac_sha256(_: &[u8]) -> String { String::new() }
+    ///
+    /// fn verify_signature(stream:&mut Stream) -> bool {
+    ///     let expected: String = hmac_sha256(stream.raw_body());
+    ///     if stream.header("X-Hub-Signature-256") != Some(expected.as_str()) {
+    ///         stream.respond_status(401u16);
+    ///         return false;
+    ///     };
+    ///
+    ///     true
+    /// }
+    ///
+    /// let routes = &[
+    ///     Route::ControlledStack(verify_signature, "webhook", &[
+    ///         Route::Post("github", |stream| stream.respond_status(200u16)),
+    ///     ]),
+    /// ];
+    /// ```
+    pub fn raw_body(&self) -> &[u8] {
+        &self.raw_body
+    }
+
+    /// Write the request body to a file, (creating or truncating it)
+    /// and return the number of bytes written. Chunked bodies are
+    /// decoded, and bodies over `max_body_size` are rejected before
+    /// the handler is called. With `BodyMode::Streaming` the body is
+    /// copied straight from the connection, without keeping it in memory.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn upload(stream:&mut Stream) {
+    ///     match stream.save_body_to("/tmp/upload.bin") {
+    ///         Ok(written) => stream.respond(201u16, Respond::new().text(&format!("{written} bytes"))),
+    ///         Err(_) => stream.respond_status(500u16),
+    ///     };
+    /// }
+    /// ```
+    pub fn save_body_to(&mut self, path: impl AsRef<Path>) -> std::io::Result<u64> {
+        let mut file: File = File::create(path)?;
+        std::io::copy(&mut self.body_reader(), &mut file)
+    }
+
+    /// Get a reader for the request body. With `BodyMode::Streaming`
+    /// the body is read from the connection as the reader is read, so
+    /// big uploads never have to fit into memory. Reading fails if a
+    /// chunked body grows bigger than `max_body_size`.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::io::Read;
+    ///
+    /// fn count_lines(stream: &mut Stream) {
+    ///     let mut lines: usize = 0;
+    ///     let mut chunk = [0u8; 8192];
+    ///     let mut reader = stream.body_reader();
+    ///     while let Ok(read @ 1..) = reader.read(&mut chunk) {
+    ///         lines += chunk[..read].iter().filter(|&&byte| byte == b'\n').count();
+    ///     }
+    ///
+    ///     stream.respond(200u16, Respond::new().text(&lines.to_string()));
+    /// }
+    /// ```
+    pub fn body_reader(&mut self) -> BodyReader<'_, 'a> {
+        BodyReader {
+            stream: self,
+            pos: 0,
+        }
+    }
+
+    /// Read a streamed body (see `BodyMode::Streaming`) into memory, so
+    /// it's available using `stream.body()` like with buffered bodies.
+    /// Does nothing if the body has been buffered already.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream: &mut Stream) {
+    ///     if stream.read_body().is_err() {
+    ///         return stream.respond_status(400u16);
+    ///     };
+    ///
+    ///     let name: String = stream.body().clone();
+    /// }
+    /// ```
+    pub fn read_body(&mut self) -> std::io::Result<&[u8]> {
+        if self.unread_body.is_some() {
+            let mut raw_body: Vec<u8> = Vec::new();
+            self.body_reader().read_to_end(&mut raw_body)?;
+            self.body = String::from_utf8_lossy(&raw_body).to_string();
+            self.raw_body = raw_body;
+            self.unread_body = None;
+        };
+
+        Ok(&self.raw_body)
+    }
+
+    /*- Read the next part of an unread body from the connection -*/
+    fn read_unread_body(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut body: UnreadBody = match self.unread_body.take() {
+            Some(e) => e,
+            None => return Ok(0),
+        };
+        let result = self.read_framed(&mut body, buf);
+        self.unread_body = Some(body);
+        result
+    }
+
+    fn read_framed(&mut self, body: &mut UnreadBody, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            match body.framing {
+                Framing::Done | Framing::Length(0) => {
+                    body.framing = Framing::Done;
+                    return Ok(0);
+                }
+                Framing::Length(left) => {
+                    /*- Never read past the body, the rest belongs to the next request -*/
+                    let read: usize = self.read_buffered(body, buf, left, left)?;
+                    body.framing = Framing::Length(left - read as u64);
+                    return Ok(read);
+                }
+                Framing::Chunked(0, first) => {
+                    /*- Chunk data is followed by a CRLF -*/
+                    if !first && !self.read_line(body)?.is_empty() {
+                        return Err(ErrorKind::InvalidData.into());
+                    };
+
+                    let line: String = self.read_line(body)?;
+                    let size: u64 = match parse_chunk_size(line.as_bytes()) {
+                        Some(e) => e,
+                        None => return Err(ErrorKind::InvalidData.into()),
+                    };
+                    if body
+                        .max
+                        .is_some_and(|max| (body.read as u64).saturating_add(size) > max as u64)
+                    {
+                        return Err(std::io::Error::new(
+                            ErrorKind::InvalidData,
+                            "body too large",
+                        ));
+                    };
+
+                    /*- The last chunk is followed by trailers, and an empty line -*/
+                    if size == 0 {
+                        let mut trailer_size: usize = 0;
+                        loop {
+                            let line: String = self.read_line(body)?;
+                            trailer_size += line.len() + 2;
+                            if trailer_size > TRAILERS_MAX {
+                                return Err(std::io::Error::new(
+                                    ErrorKind::InvalidData,
+                                    "trailers too large",
+                                ));
+                            };
+                            match line.split_once(':') {
+                                Some((key, value)) => self
+                                    .trailers
+                                    .push((key.to_string(), value.trim().to_string())),
+                                None if line.is_empty() => break,
+                                None => continue,
+                            };
+                        }
+                        body.framing = Framing::Done;
+                        return Ok(0);
+                    };
+                    body.framing = Framing::Chunked(size, false);
+                }
+                Framing::Chunked(left, _) => {
+                    let read: usize = self.read_buffered(body, buf, left, CHUNK_READ as u64)?;
+                    body.framing = Framing::Chunked(left - read as u64, false);
+                    return Ok(read);
+                }
+            }
+        }
+    }
+
+    /*- Copy at most `left` buffered bytes into buf, reading more
+    from the connection (at most `limit` bytes) if needed -*/
+    fn read_buffered(
+        &mut self,
+        body: &mut UnreadBody,
+        buf: &mut [u8],
+        left: u64,
+        limit: u64,
+    ) -> std::io::Result<usize> {
+        if body.pos == body.buffered.len() {
+            self.fill(body, limit)?;
+        };
+        let read: usize = buf
+            .len()
+            .min(body.buffered.len() - body.pos)
+            .min(usize::try_from(left).unwrap_or(usize::MAX));
+        buf[..read].copy_from_slice(&body.buffered[body.pos..body.pos + read]);
+        body.pos += read;
+        body.read += read;
+        Ok(read)
+    }
+
+    /*- Read a CRLF terminated line, (without the CRLF) -*/
+    fn read_line(&mut self, body: &mut UnreadBody) -> std::io::Result<String> {
+        loop {
+            if let Some(end) = body.buffered[body.pos..]
+                .windows(2)
+                .position(|window| window == b"\r\n")
+            {
+                let line = String::from_utf8_lossy(&body.buffered[body.pos..body.pos + end]);
+                let line: String = line.to_string();
+                body.pos += end + 2;
+                return Ok(line);
+            } else if body.buffered.len() - body.pos > CHUNK_LINE_MAX {
+                return Err(ErrorKind::InvalidData.into());
+            };
+            self.fill(body, CHUNK_READ as u64)?;
+        }
+    }
+
+    /*- Read more bytes from the connection into the buffer -*/
+    fn fill(&mut self, body: &mut UnreadBody, limit: u64) -> std::io::Result<()> {
+        /*- Bytes which have been consumed are dropped, so
+        that the buffer doesn't grow with every line read -*/
+        body.buffered.drain(..body.pos);
+        body.pos = 0;
+
+        let mut chunk = [0u8; CHUNK_READ];
+        let limit: usize = chunk
+            .len()
+            .min(usize::try_from(limit).unwrap_or(usize::MAX));
+        match self.stream_inner.get_mut().read(&mut chunk[..limit])? {
+            0 => Err(ErrorKind::UnexpectedEof.into()),
+            read => {
+                body.buffered.extend_from_slice(&chunk[..read]);
+                Ok(())
+            }
+        }
+    }
+
+    /// Decode the body using the charset declared in the `Content-Type`
+    /// header. Supports `utf-8`, `iso-8859-1` (latin1) and `us-ascii`,
+    /// other charsets (or no charset at all) are decoded as utf-8.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream:&mut Stream) {
+    ///     match stream.body_text() {
+    ///         Ok(text) => stream.respond(200u16, Respond::new().text(&text)),
+    ///         Err(_) => stream.respond_status(400u16),
+    ///     }
+    /// }
+    /// ```
+    pub fn body_text(&self) -> Result<String, CharsetError> {
+        let charset: Option<String> = self.header("Content-Type").and_then(|content_type| {
+            content_type
+                .split(';')
+                .skip(1)
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("charset"))
+                .map(|(_, value)| value.trim().trim_matches('"').to_ascii_lowercase())
+        });
+
+        match charset.as_deref() {
+            /*- Latin1 bytes map directly to the first 256 unicode code points -*/
+            Some("iso-8859-1" | "latin1" | "latin-1" | "l1") => {
+                Ok(self.raw_body.iter().map(|&byte| byte as char).collect())
+            }
+            Some("us-ascii" | "ascii") => match self.raw_body.is_ascii() {
+                true => Ok(self.raw_body.iter().map(|&byte| byte as char).collect()),
+                false => Err(CharsetError::InvalidAscii),
+            },
+            _ => String::from_utf8(self.raw_body.clone()).map_err(CharsetError::InvalidUtf8),
+        }
     }
 
     /// Aquire a reference to the URL-parameters
@@ -434,14 +1960,505 @@ impl<'a> Stream<'a> {
         &self.params
     }
 
+    /// Get an URL-parameter parsed as some type
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /* Route::Get("users/:id:u64:", endpoint) */
+    /// fn endpoint(stream: &mut Stream) {
+    ///     let id: u64 = match stream.param_as("id") {
+    ///         Ok(id) => id,
+    ///         Err(_) => return stream.respond_status(400u16),
+    ///     };
+    /// }
+    /// ```
+    pub fn param_as<T: FromStr>(&self, name: &str) -> Result<T, ParamError> {
+        match self.params.get(name) {
+            Some(value) => value.parse::<T>().map_err(|_| ParamError::Invalid),
+            None => Err(ParamError::Missing),
+        }
+    }
+
     /// Aquire a reference to the request headers
     pub fn headers(&self) -> &HashMap<&str, &str> {
-        &self.headers
+        self.headers_map
+            .get_or_init(|| self.headers.iter().copied().collect())
+    }
+
+    /// Copy the request headers into owned strings, which aren't tied
+    /// to the request. Useful for passing them to another thread, or
+    /// storing them in shared state. Later headers override earlier
+    /// ones, like in `headers()`.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::collections::HashMap;
+    ///
+    /// fn endpoint(stream: &mut Stream) {
+    ///     let headers: HashMap<String, String> = stream.headers_owned();
+    ///     std::thread::spawn(move || {
+    ///         println!("{:?}", headers.get("User-Agent"));
+    ///     });
+    /// }
+    /// ```
+    pub fn headers_owned(&self) -> HashMap<String, String> {
+        self.headers
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Get the value of a request header. Header names are case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        /*- Later headers override earlier ones, like in `headers()` -*/
+        self.headers
+            .iter()
+            .rev()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+
+    /// Get the value of a trailer, (a header sent after a chunked
+    /// request body). Trailer names are case-insensitive
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn upload(stream: &mut Stream) {
+    ///     let checksum: Option<&str> = stream.trailer("X-Checksum");
+    /// }
+    /// ```
+    pub fn trailer(&self, name: &str) -> Option<&str> {
+        self.trailers
+            .iter()
+            .rev()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The requested host, without the port. Taken from the request
+    /// target if it's an absolute url, (ex `GET http://example.com/ HTTP/1.1`),
+    /// otherwise from the `Host` header.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream: &mut Stream) {
+    ///     /* "Host: example.com:8080" */
+    ///     assert_eq!(stream.host(), Some("example.com"));
+    ///     assert_eq!(stream.host_with_port(), Some("example.com:8080"));
+    /// }
+    /// ```
+    pub fn host(&self) -> Option<&str> {
+        let host: &str = self.host_with_port()?;
+
+        /*- Colons inside brackets are part of ipv6 addresses -*/
+        match host.rfind(':') {
+            Some(index) if !host[index..].contains(']') => Some(&host[..index]),
+            _ => Some(host),
+        }
+    }
+
+    /// The requested host, including the port if one was specified
+    pub fn host_with_port(&self) -> Option<&str> {
+        let authority: Option<&str> = self
+            .path
+            .strip_prefix("http://")
+            .or_else(|| self.path.strip_prefix("https://"))
+            .and_then(|rest| rest.split(['/', '?']).next())
+            .and_then(|authority| authority.rsplit('@').next());
+
+        match authority {
+            Some(authority) if !authority.is_empty() => Some(authority),
+            _ => self
+                .header("Host")
+                .map(|host| host.trim())
+                .filter(|host| !host.is_empty()),
+        }
+    }
+
+    /// The entity tags of the `If-Match` header, as written in the request,
+    /// (like `"abc"`, `W/"abc"` or `*`) so they can be compared to the
+    /// `ETag` of the resource. Used for optimistic concurrency, where
+    /// updates should only happen if the resource hasn't changed.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn update(stream: &mut Stream) {
+    ///     let current_etag = "\"v2\"";
+    ///     if let Some(tags) = stream.if_match() {
+    ///         if !tags.iter().any(|tag| tag == "*" || tag == current_etag) {
+    ///             return stream.precondition_failed();
+    ///         };
+    ///     };
+    ///
+    ///     /* Update the resource... */
+    /// }
+    /// ```
+    pub fn if_match(&self) -> Option<Vec<String>> {
+        self.header("If-Match").map(headers::parse_etags)
+    }
+
+    /// The entity tags of the `If-None-Match` header, as written in the
+    /// request. See `if_match`.
+    pub fn if_none_match(&self) -> Option<Vec<String>> {
+        self.header("If-None-Match").map(headers::parse_etags)
+    }
+
+    /// Respond with `412 Precondition Failed`, for when conditional
+    /// headers like `If-Match` don't match the current resource
+    pub fn precondition_failed(&mut self) {
+        self.respond_status(412u16);
+    }
+
+    /// The request line and headers as they were recieved, (including
+    /// the blank line which ends them) before being parsed. Useful for
+    /// debugging malformed requests. Invalid utf-8 is replaced with `�`.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream: &mut Stream) {
+    ///     /* "GET /endpoint HTTP/1.1\r\nHost: localhost\r\n\r\n" */
+    ///     println!("{:?}", stream.raw_request());
+    /// }
+    /// ```
+    pub fn raw_request(&self) -> &str {
+        self.raw_request
+    }
+
+    /// The http-method of the request
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The requested path, without the query string
+    pub fn path(&self) -> &str {
+        self.path
+    }
+
+    /// The query string of the request, (everything after the `?`
+    /// in the path) as it was sent. Empty if there is none
+    pub fn query_string(&self) -> &str {
+        self.query
+    }
+
+    /// The decoded keys and values of the query string, for requests
+    /// with any method. Keys without a value, (ex `?debug`) get an empty one
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// /* POST /search?page=2 */
+    /// fn search(stream:&mut Stream) {
+    ///     let page: u64 = stream
+    ///         .query()
+    ///         .get("page")
+    ///         .and_then(|page| page.parse().ok())
+    ///         .unwrap_or(1);
+    ///     let filters: &str = stream.body();
+    /// }
+    /// ```
+    pub fn query(&self) -> HashMap<String, String> {
+        url::parse_query(self.query)
+    }
+
+    /// The path of the route which matched the request, including the
+    /// paths of the stacks it's in, and with url parameters left as they
+    /// were written, (ex `users/:id:u64:/posts`). Useful for grouping
+    /// logs and metrics by route. `None` if no route matched.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// fn on_end(stream: &Stream, status: Option<u16>, duration: Duration) {
+    ///     let route: &str = stream.route_template().unwrap_or("(unmatched)");
+    ///     println!("{route} {status:?} {duration:?}");
+    /// }
+    /// ```
+    pub fn route_template(&self) -> Option<&str> {
+        self.route_template.as_deref()
+    }
+
+    /// Time passed since the server started processing the request
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream:&mut Stream) {
+    ///     let dur = stream.elapsed().as_secs_f64() * 1000.0;
+    ///     stream.respond(
+    ///         200u16,
+    ///         Respond::new()
+    ///             .text("Hello, world!")
+    ///             .headers(vec![format!("Server-Timing: app;dur={dur:.1}")]),
+    ///     );
+    /// }
+    /// ```
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// When the request should be finished by, if the server has a
+    /// `request_timeout`. Handlers can use it to budget their own work,
+    /// like bounding a call to another service, and respond early
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// fn endpoint(stream:&mut Stream) {
+    ///     let remaining: Duration = stream
+    ///         .deadline()
+    ///         .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    ///         .unwrap_or(Duration::from_secs(10));
+    ///
+    ///     if remaining < Duration::from_millis(100) {
+    ///         return stream.respond_status(503u16);
+    ///     };
+    /// }
+    /// ```
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Attach a value to the request, which later origin control functions
+    /// and the endpoint can read using `stream.extension()`. One value is
+    /// stored per type, so the previous value of the type is returned
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// struct User { name: String }
+    ///
+    /// fn auth(stream:&mut Stream) -> bool {
+    ///     match stream.header("Authorization") {
+    ///         Some(token) => {
+    ///             stream.insert_extension(User { name: token.to_string() });
+    ///             true
+    ///         },
+    ///         None => {
+    ///             stream.respond_status(401u16);
+    ///             false
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let routes = &[
+    ///     Route::ControlledStack(auth, "account", &[
+    ///         Route::Get("name", |stream| {
+    ///             let name: String = stream.extension::<User>().unwrap().name.clone();
+    ///             stream.respond(200u16, Respond::new().text(&name));
+    ///         }),
+    ///     ]),
+    /// ];
+    /// ```
+    pub fn insert_extension<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.extensions
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.downcast::<T>().ok())
+            .map(|previous| *previous)
+    }
+
+    /// Get the value of a type which was attached to the
+    /// request using `stream.insert_extension()`
+    pub fn extension<T: 'static>(&self) -> Option<&T> {
+        self.extensions
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+    }
+
+    /// Decide if the connection may be reused for another request after
+    /// this one, (which is checked once the handler returns). Handlers which
+    /// send unbounded bodies, or switch protocols, can use this to close the
+    /// connection. Connections which the server wouldn't reuse, (like when
+    /// keep-alive is disabled) can't be kept open using this.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn endpoint(stream:&mut Stream) {
+    ///     stream.set_keep_alive(false);
+    ///     stream.respond_status(200u16);
+    /// }
+    /// ```
+    pub fn set_keep_alive(&mut self, keep_alive: bool) {
+        self.keep_alive &= keep_alive;
+    }
+
+    /// If a response has already been sent. Origin control functions and
+    /// endpoints can check this to avoid responding twice, (only the first
+    /// response is sent to the client, later ones are ignored)
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    ///
+    /// fn origin_control(stream:&mut Stream) -> bool {
+    ///     if stream.expect_headers(&["token"]) { return false; };
+    ///
+    ///     /* Passes if nothing has responded yet */
+    ///     !stream.responded()
+    /// }
+    /// ```
+    pub fn responded(&self) -> bool {
+        self.buf_written_to
+    }
+
+    /// The status code of the response, if one has been sent. Responses
+    /// which were proxied, or connections which were closed without
+    /// responding, don't have a status.
+    ///
+    /// ## Example
+    /// ```
+    /// use responder::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// fn on_end(stream: &Stream, status: Option<u16>, duration: Duration) {
+    ///     assert_eq!(status, stream.status());
+    /// }
+    /// ```
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    /// If writing the response to the client has failed, most likely
+    /// because the client disconnected. Handlers which generate long
+    /// responses can check this to stop early.
+    pub fn disconnected(&self) -> bool {
+        self.disconnected
     }
 
     /// Should not be used. Is only used internally during startup
-    pub fn enable_cors(&mut self) -> () {
-        self.cors = true
+    pub fn enable_cors(&mut self) {
+        self.cors = Some(Cors::PERMISSIVE)
+    }
+
+    /*- Cors config is overridden by CorsStacks during routing -*/
+    pub(crate) fn cors(&self) -> Option<Cors> {
+        self.cors
+    }
+    pub(crate) fn set_cors(&mut self, cors: Option<Cors>) {
+        self.cors = cors;
+    }
+
+    /*- Get the cors headers to append to responses, if cors is enabled -*/
+    fn cors_headers(&self) -> String {
+        match self.cors {
+            Some(cors) => cors.headers(),
+            None => String::new(),
+        }
+    }
+
+    /*- Log failed writes (set from the server config) -*/
+    pub(crate) fn set_logs(&mut self, logs: bool) {
+        self.logs = logs;
+    }
+
+    /*- Headers sent with every response (set from the server config) -*/
+    pub(crate) fn set_default_headers(&mut self, default_headers: &'a [(String, String)]) {
+        self.default_headers = default_headers;
+    }
+
+    /*- Keep the start of the response body (set from the server config) -*/
+    pub(crate) fn set_capture_limit(&mut self, capture_limit: usize) {
+        self.capture_limit = capture_limit;
+    }
+    pub(crate) fn captured_response(&self) -> &[u8] {
+        &self.captured_response
+    }
+
+    /*- If response header names are written in Title-Case (set from the server config) -*/
+    pub(crate) fn set_canonicalize_headers(&mut self, canonicalize_headers: bool) {
+        self.canonicalize_headers = canonicalize_headers;
+    }
+
+    /*- The request line and headers, before parsing -*/
+    pub(crate) fn set_raw_request(&mut self, raw_request: &'a str) {
+        self.raw_request = raw_request;
+    }
+
+    /*- If the connection can be reused after this request -*/
+    pub(crate) fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+    pub(crate) fn enable_keep_alive(&mut self) {
+        self.keep_alive = true;
+    }
+
+    /*- Requests should be finished `timeout` after they started (set from the server config) -*/
+    pub(crate) fn set_deadline(&mut self, timeout: Option<Duration>) {
+        self.deadline = timeout.map(|timeout| self.start + timeout);
+    }
+
+    /*- What templates do with missing keys (set from the server config) -*/
+    pub(crate) fn set_missing_keys(&mut self, missing_keys: MissingKeys) {
+        self.missing_keys = missing_keys;
+    }
+
+    /*- The body format of status responses (set from the server config) -*/
+    pub(crate) fn set_error_format(&mut self, error_format: ErrorFormat) {
+        self.error_format = error_format;
+    }
+
+    /*- The html template of error responses (set from the server config) -*/
+    pub(crate) fn set_error_page_template(&mut self, error_page_template: Option<&'static str>) {
+        self.error_page_template = error_page_template;
+    }
+
+    /*- The type of files which can't be guessed (set from the server config) -*/
+    pub(crate) fn set_default_content_type(&mut self, default_content_type: Option<ResponseType>) {
+        self.default_content_type = default_content_type;
+    }
+
+    /*- The response type of a file by its extension, or by its first bytes if the
+    extension is unknown or missing. Falls back to the server's default type -*/
+    pub(crate) fn file_type(&self, path: &Path, file: &mut (impl Read + Seek)) -> ResponseType {
+        if let Some(response_type) = ResponseType::from_extension(path) {
+            return response_type;
+        };
+
+        let mut start = [0u8; 16];
+        let read: usize = file.read(&mut start).unwrap_or(0);
+        file.seek(SeekFrom::Start(0)).ok();
+        ResponseType::guess_bytes(&start[..read])
+            .or(self.default_content_type)
+            .unwrap_or_else(|| ResponseType::guess(path))
+    }
+
+    /*- Html template for redirects (set from the server config) -*/
+    pub(crate) fn set_redirect_body(&mut self, redirect_body: Option<fn(&str) -> String>) {
+        self.redirect_body = redirect_body;
+    }
+}
+
+impl Read for BodyReader<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        /*- Buffered bodies are read from memory -*/
+        if self.stream.unread_body.is_none() {
+            let rest: &[u8] = &self.stream.raw_body[self.pos.min(self.stream.raw_body.len())..];
+            let read: usize = rest.len().min(buf.len());
+            buf[..read].copy_from_slice(&rest[..read]);
+            self.pos += read;
+            return Ok(read);
+        };
+
+        self.stream.read_unread_body(buf)
     }
 }
 
@@ -450,12 +2467,39 @@ impl<'a> From<TcpStream> for Stream<'a> {
     /// Convert TcpStream into Stream struct.
     fn from(stream_inner: TcpStream) -> Self {
         Self {
-            cors: false,
-            stream_inner,
+            cors: None,
+            disconnected: false,
+            logs: false,
+            redirect_body: None,
+            default_headers: &[],
+            canonicalize_headers: false,
+            reason: None,
+            keep_alive: false,
+            raw_request: "",
+            trailers: Vec::new(),
+            unread_body: None,
+            route_template: None,
+            vary: Vec::new(),
+            default_content_type: None,
+            error_format: ErrorFormat::Text,
+            error_page_template: None,
+            extensions: HashMap::new(),
+            captured_response: Vec::new(),
+            capture_limit: 0,
+            missing_keys: MissingKeys::Empty,
+            stream_inner: BufWriter::new(stream_inner),
             buf_written_to: false,
+            status: None,
             body: String::new(),
+            raw_body: Vec::new(),
             params: HashMap::new(),
-            headers: HashMap::new(),
+            headers: Vec::new(),
+            headers_map: OnceCell::new(),
+            method: Method::UNKNOWN,
+            path: "",
+            query: "",
+            start: Instant::now(),
+            deadline: None,
         }
     }
 }
@@ -1,21 +1,24 @@
 /*- Imports -*/
 use std::{
     sync::{
+        atomic::{AtomicBool, Ordering},
         mpsc::{self, Receiver, Sender},
         Arc, Mutex,
     },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 /*- Structs, enums & unions -*/
-#[allow(dead_code)]
 pub struct MainThreadHandler {
     threads: Vec<Worker>,
     sender: mpsc::Sender<Task>,
+
+    /*- When set, workers drop tasks instead of executing them -*/
+    discard: Arc<AtomicBool>,
 }
 
 /*- Handles a connection -*/
-#[allow(dead_code)]
 pub struct Worker {
     thread: JoinHandle<()>,
 }
@@ -25,7 +28,7 @@ pub type Task = Box<dyn FnOnce() + Send + 'static>;
 
 /*- Method implementations -*/
 impl MainThreadHandler {
-    pub fn new(num_threads: u16) -> Self {
+    pub fn new(num_threads: u16, name_prefix: &str) -> Self {
         /*- Needs to be bigger than 0 -*/
         if num_threads < 1 {
             panic!("Number of threads must be bigger than 0");
@@ -34,15 +37,24 @@ impl MainThreadHandler {
         /*- Open mpsc channel -*/
         let (sender, rcv): (Sender<Task>, Receiver<Task>) = mpsc::channel::<Task>();
         let rcv = Arc::new(Mutex::new(rcv));
+        let discard: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
 
         /*- Initialize threads and allocate the right amount of memory -*/
         let mut threads: Vec<Worker> = Vec::with_capacity(num_threads as usize);
-        for _ in 0..num_threads {
-            threads.push(Worker::new(Arc::clone(&rcv)));
+        for index in 0..num_threads {
+            threads.push(Worker::new(
+                Arc::clone(&rcv),
+                Arc::clone(&discard),
+                format!("{name_prefix}-{index}"),
+            ));
         }
 
         /*- Return -*/
-        MainThreadHandler { threads, sender }
+        MainThreadHandler {
+            threads,
+            sender,
+            discard,
+        }
     }
 
     pub fn exec<T>(&self, t: T)
@@ -54,10 +66,37 @@ impl MainThreadHandler {
         /*- Send the job down the channel -*/
         self.sender.send(task).unwrap_or(());
     }
+
+    /*- Stop taking new tasks, and wait for the workers to finish the tasks
+    they've already got. Tasks still queued after the drain timeout are dropped.
+    Workers still busy after the timeout are left running -*/
+    pub fn join(self, timeout: Duration, drain_timeout: Option<Duration>) {
+        /*- Workers exit once the channel is closed and empty -*/
+        drop(self.sender);
+
+        let deadline = Instant::now() + timeout;
+        let drain_deadline = drain_timeout.map(|drain_timeout| Instant::now() + drain_timeout);
+        for worker in self.threads {
+            while !worker.thread.is_finished() {
+                if drain_deadline.is_some_and(|drain_deadline| Instant::now() >= drain_deadline) {
+                    self.discard.store(true, Ordering::SeqCst);
+                };
+                if Instant::now() >= deadline {
+                    return;
+                };
+                thread::sleep(Duration::from_millis(10));
+            }
+            worker.thread.join().ok();
+        }
+    }
 }
 impl Worker {
-    pub fn new(reciever: Arc<Mutex<Receiver<Task>>>) -> Self {
-        let thread = thread::spawn(move || loop {
+    pub fn new(
+        reciever: Arc<Mutex<Receiver<Task>>>,
+        discard: Arc<AtomicBool>,
+        name: String,
+    ) -> Self {
+        let spawned = thread::Builder::new().name(name).spawn(move || loop {
             /*- Get the task -*/
             let task = match match reciever.lock() {
                 Ok(v) => v,
@@ -69,9 +108,15 @@ impl Worker {
                 Err(_) => return,
             };
 
-            /*- Execute task -*/
-            task();
+            /*- Execute task, unless the queue is being discarded -*/
+            if !discard.load(Ordering::SeqCst) {
+                task();
+            };
         });
+        let thread = match spawned {
+            Ok(e) => e,
+            Err(_) => panic!("Failed to spawn worker thread"),
+        };
 
         /*- Return -*/
         Worker { thread }
@@ -1,31 +1,365 @@
 /*- Modules -*/
 pub mod headers {
 
+    /*- Parse a data buffer into a list of header names and values. Requests
+    seldom have many headers, so a list is cheaper to build than a hashmap.
+    Returns None if there are more headers than `max_headers` -*/
+    pub fn parse_headers(request: &str, max_headers: usize) -> Option<Vec<(&str, &str)>> {
+        let mut headers: Vec<(&str, &str)> = Vec::new();
+
+        /*- Skip the request line -*/
+        for line in request.split("\r\n").skip(1) {
+            let (k, v) = match line.split_once(':') {
+                Some(e) => e,
+                None => continue,
+            };
+            if headers.len() == max_headers {
+                return None;
+            };
+            headers.push((k, v.trim_start()));
+        }
+
+        Some(headers)
+    }
+
+    /*- Check if an Accept header, (ex "text/html, application/json;q=0.8") accepts
+    a media type. Ranges with q=0 explicitly refuse the media type -*/
+    pub fn accepts(accept: &str, media_type: &str) -> bool {
+        let (kind, _) = media_type.split_once('/').unwrap_or((media_type, ""));
+
+        accept.split(',').any(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let range: &str = parts.next().unwrap_or("");
+            let refused: bool = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+
+            !refused
+                && (range == "*/*"
+                    || range.eq_ignore_ascii_case(media_type)
+                    || range
+                        .strip_suffix("/*")
+                        .is_some_and(|range_kind| range_kind.eq_ignore_ascii_case(kind)))
+        })
+    }
+
+    /*- Write a header name in Title-Case, (ex "content-type" -> "Content-Type") -*/
+    pub fn canonicalize(name: &str) -> String {
+        name.split('-')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => {
+                        first.to_ascii_uppercase().to_string()
+                            + &chars.as_str().to_ascii_lowercase()
+                    }
+                    None => String::new(),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("-")
+    }
+
+    /*- Find the index where the headers end (after the blank line), if they've all been recieved -*/
+    pub fn find_header_end(buffer: &[u8]) -> Option<usize> {
+        buffer
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .map(|pos| pos + 4)
+    }
+
+    /*- Parse a list of entity tags, (ex If-Match: "a", W/"b"). Commas
+    inside of quotes are part of the tag, and not separators -*/
+    pub fn parse_etags(value: &str) -> Vec<String> {
+        let mut tags: Vec<String> = Vec::new();
+        let mut tag: String = String::new();
+        let mut quoted: bool = false;
+
+        for c in value.chars() {
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    tag.push(c);
+                }
+                ',' if !quoted => tags.push(std::mem::take(&mut tag)),
+                _ => tag.push(c),
+            }
+        }
+        tags.push(tag);
+
+        tags.into_iter()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+
+    /*- Parse a single byte range, (ex "bytes=0-499") into the first and last byte
+    position. Returns None if the range should be ignored, (like multiple ranges
+    or other units), and Some(Err) if the range can't be satisfied -*/
+    pub fn parse_range(value: &str, length: u64) -> Option<Result<(u64, u64), ()>> {
+        let range: &str = value.trim().strip_prefix("bytes=")?;
+        if range.contains(',') {
+            return None;
+        };
+        let (start, end) = range.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+
+        /*- Suffix ranges, (ex "bytes=-500") request the last n bytes -*/
+        if start.is_empty() {
+            let suffix: u64 = end.parse().ok()?;
+            if suffix == 0 || length == 0 {
+                return Some(Err(()));
+            };
+            return Some(Ok((length.saturating_sub(suffix), length - 1)));
+        };
+
+        let start: u64 = start.parse().ok()?;
+        let end: u64 = match end {
+            "" => length.saturating_sub(1),
+            end => end.parse::<u64>().ok()?.min(length.saturating_sub(1)),
+        };
+        if start >= length {
+            return Some(Err(()));
+        } else if end < start {
+            return None;
+        };
+
+        Some(Ok((start, end)))
+    }
+}
+
+pub mod date {
+
+    /*- Imports -*/
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    /*- Format a time as an http-date, (ex "Sun, 06 Nov 1994 08:49:37 GMT") -*/
+    pub fn http_date(time: SystemTime) -> String {
+        let secs: u64 = time
+            .duration_since(UNIX_EPOCH)
+            .map(|e| e.as_secs())
+            .unwrap_or(0);
+        let days: u64 = secs / 86400;
+        let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+
+        /*- Convert days since epoch into a civil date -*/
+        let z: u64 = days + 719468;
+        let era: u64 = z / 146097;
+        let doe: u64 = z - era * 146097;
+        let yoe: u64 = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy: u64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp: u64 = (5 * doy + 2) / 153;
+        let day: u64 = doy - (153 * mp + 2) / 5 + 1;
+        let month: u64 = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year: u64 = yoe + era * 400 + u64::from(month <= 2);
+
+        format!(
+            "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+            DAYS[(days % 7) as usize],
+            day,
+            MONTHS[(month - 1) as usize],
+            year,
+            hour,
+            minute,
+            second
+        )
+    }
+}
+
+pub mod html {
+
     /*- Imports -*/
     use std::collections::HashMap;
 
-    /*- Parse a data buffer into an hashmap containing headers -*/
-    pub fn parse_headers(request: &str) -> HashMap<&str, &str> {
-        /*- Create the hashmap -*/
-        let mut end: HashMap<&str, &str> = HashMap::new();
+    /*- Escape a string, so that it can be written inside of html -*/
+    pub fn escape(value: &str) -> String {
+        let mut escaped: String = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    /*- Substitute {{key}} placeholders with html escaped values from the context,
+    and {{{key}}} placeholders with raw values. Missing keys render empty, unless
+    they aren't allowed, in which case the first missing key is returned -*/
+    pub fn render<'t>(
+        template: &'t str,
+        context: &HashMap<String, String>,
+        allow_missing: bool,
+    ) -> Result<String, &'t str> {
+        let mut output: String = String::with_capacity(template.len());
+        let mut rest: &str = template;
 
-        /*- Iterate over lines -*/
-        for line in request.split("\r\n") {
-            let (k, v) = match line.split_once(':') {
-                Some(e) => e,
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+            let placeholder: &str = &rest[start..];
+            let (open, close) = match placeholder.starts_with("{{{") {
+                true => ("{{{", "}}}"),
+                false => ("{{", "}}"),
+            };
+
+            /*- Unclosed placeholders are left as they are -*/
+            let end: usize = match placeholder[open.len()..].find(close) {
+                Some(e) => open.len() + e,
                 None => {
+                    rest = placeholder;
+                    break;
+                }
+            };
+            let key: &str = placeholder[open.len()..end].trim();
+            match context.get(key) {
+                Some(value) if open == "{{{" => output.push_str(value),
+                Some(value) => output.push_str(&escape(value)),
+                None if allow_missing => (),
+                None => return Err(key),
+            };
+            rest = &placeholder[end + close.len()..];
+        }
+
+        output.push_str(rest);
+        Ok(output)
+    }
+}
+
+pub mod url {
+
+    /*- Imports -*/
+    use std::collections::HashMap;
+
+    /*- Decode a percent-encoded url component, where `+` also means a space.
+    Invalid escapes are kept as they are -*/
+    pub fn decode(value: &str) -> String {
+        let bytes: &[u8] = value.as_bytes();
+        let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+        let mut index: usize = 0;
+
+        while index < bytes.len() {
+            let hex = bytes
+                .get(index + 1..index + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match (bytes[index], hex) {
+                (b'%', Some(byte)) => {
+                    decoded.push(byte);
+                    index += 3;
                     continue;
                 }
+                (b'+', _) => decoded.push(b' '),
+                (byte, _) => decoded.push(byte),
             };
+            index += 1;
+        }
 
-            /*- Add k and v to hashmap -*/
-            match end.insert(k, v.trim_start()) {
-                Some(e) => e,
-                None => continue,
+        String::from_utf8_lossy(&decoded).to_string()
+    }
+
+    /*- Parse a query string, (ex "page=2&sort=name") into decoded keys
+    and values. Keys without a value get an empty one, and later keys
+    override earlier ones -*/
+    pub fn parse_query(query: &str) -> HashMap<String, String> {
+        query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode(key), decode(value))
+            })
+            .collect()
+    }
+}
+
+pub mod json {
+
+    /*- Escape a string, so that it can be written inside of a JSON string -*/
+    pub fn escape(value: &str) -> String {
+        let mut escaped: String = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '"' => escaped.push_str("\\\""),
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+}
+
+#[cfg(feature = "gzip")]
+pub mod encoding {
+
+    /*- Imports -*/
+    use flate2::{
+        read::{MultiGzDecoder, ZlibDecoder},
+        write::GzEncoder,
+        Compression,
+    };
+    use std::io::{Read, Write};
+
+    /*- Decode a request body which was sent with a Content-Encoding. Decoding stops
+    after `max` bytes, so small compressed bodies can't expand into huge ones. Returns
+    the status to respond with if the body can't (or isn't allowed to) be decoded -*/
+    pub fn decode_body(content_encoding: &str, body: &[u8], max: usize) -> Result<Vec<u8>, u16> {
+        let mut decoded: Vec<u8> = body.to_vec();
+
+        /*- Codings are listed in the order they were applied -*/
+        for coding in content_encoding.rsplit(',').map(str::trim) {
+            let reader: Box<dyn Read + '_> = match &*coding.to_ascii_lowercase() {
+                "gzip" | "x-gzip" => Box::new(MultiGzDecoder::new(&decoded[..])),
+                "deflate" => Box::new(ZlibDecoder::new(&decoded[..])),
+                "identity" | "" => continue,
+                _ => return Err(415u16),
+            };
+
+            let mut output: Vec<u8> = Vec::new();
+            if reader
+                .take(max as u64 + 1)
+                .read_to_end(&mut output)
+                .is_err()
+            {
+                return Err(400u16);
             };
+            if output.len() > max {
+                return Err(413u16);
+            };
+            decoded = output;
         }
 
-        /*- Return */
-        end
+        Ok(decoded)
+    }
+
+    /*- Gzip compress data, (like files for the file cache) -*/
+    pub fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut encoder: GzEncoder<Vec<u8>> = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+
+    /*- Decompress gzip data, stopping after `max` bytes -*/
+    pub fn gunzip(data: &[u8], max: usize) -> std::io::Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        MultiGzDecoder::new(data)
+            .take(max as u64)
+            .read_to_end(&mut output)?;
+        Ok(output)
     }
 }
@@ -0,0 +1,226 @@
+use responder::prelude::*;
+use responder::request::BodyMode;
+use responder::response::ResponseType;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Once;
+use std::thread;
+use std::time::Duration;
+
+const PORT: u16 = 38471;
+const STREAMING_PORT: u16 = 38472;
+
+const ROUTES: &[Route] = &[
+    Route::Post("echo", |stream| {
+        if stream.read_body().is_err() {
+            return stream.respond_status(400u16);
+        };
+        let body: Vec<u8> = stream.raw_body().to_vec();
+        stream.respond_bytes(200u16, &body, ResponseType::Text);
+    }),
+    Route::Post("trailer", |stream| {
+        if stream.read_body().is_err() {
+            return stream.respond_status(400u16);
+        };
+        let checksum: String = stream.trailer("X-Checksum").unwrap_or("").to_string();
+        stream.respond(200u16, Respond::new().text(&checksum));
+    }),
+];
+
+/*- Connect to a server echoing request bodies. The servers are started by the first
+test, and have a single worker, so a request which kills it fails all others -*/
+fn connect(port: u16) -> TcpStream {
+    static START: Once = Once::new();
+    START.call_once(|| {
+        for (port, body_mode) in [
+            (PORT, BodyMode::Buffered),
+            (STREAMING_PORT, BodyMode::Streaming),
+        ] {
+            thread::spawn(move || {
+                Server::new()
+                    .address("127.0.0.1")
+                    .port(port)
+                    .threads(1)
+                    .no_logs()
+                    .body_mode(body_mode)
+                    .keep_alive(Duration::from_secs(5))
+                    .routes(ROUTES)
+                    .start()
+                    .unwrap();
+            });
+        }
+    });
+
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            return stream;
+        };
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!("server didn't start");
+}
+
+/*- Send a request in parts, and read responses until the server closes the connection -*/
+fn exchange(port: u16, parts: &[&[u8]]) -> String {
+    let mut stream: TcpStream = connect(port);
+    for part in parts {
+        stream.write_all(part).unwrap();
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let mut response: Vec<u8> = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+fn chunked(body: &str) -> String {
+    exchange(
+        PORT,
+        &[
+            b"POST /echo HTTP/1.1\r\nConnection: close\r\nTransfer-Encoding: chunked\r\n\r\n",
+            body.as_bytes(),
+        ],
+    )
+}
+
+fn status(response: &str) -> &str {
+    response.split(' ').nth(1).unwrap_or("")
+}
+
+#[test]
+fn decodes_chunked_bodies() {
+    let response: String = chunked("5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n");
+    assert_eq!(status(&response), "200");
+    assert!(response.ends_with("\r\n\r\nhello, world"));
+}
+
+#[test]
+fn skips_chunk_extensions() {
+    let response: String = chunked("5;name=value\r\nhello\r\nA; last\r\n, world!!!\r\n0\r\n\r\n");
+    assert_eq!(status(&response), "200");
+    assert!(response.ends_with("\r\n\r\nhello, world!!!"));
+}
+
+#[test]
+fn reads_chunks_split_over_reads() {
+    let response: String = exchange(
+        PORT,
+        &[
+            b"POST /echo HTTP/1.1\r\nConnection: close\r\nTransfer-Encoding: chunked\r\n\r\n5\r",
+            b"\nhel",
+            b"lo\r\n0\r",
+            b"\n\r\n",
+        ],
+    );
+    assert_eq!(status(&response), "200");
+    assert!(response.ends_with("\r\n\r\nhello"));
+}
+
+#[test]
+fn refuses_invalid_chunk_sizes() {
+    /*- Sizes bigger than the default limit, or which don't fit into a u64 -*/
+    assert_eq!(
+        status(&chunked("ffffffffffffffff\r\nhello\r\n0\r\n\r\n")),
+        "413"
+    );
+    assert_eq!(
+        status(&chunked("fffffffffffffffffff\r\nhello\r\n0\r\n\r\n")),
+        "400"
+    );
+    assert_eq!(status(&chunked("+5\r\nhello\r\n0\r\n\r\n")), "400");
+    assert_eq!(status(&chunked("\r\nhello\r\n0\r\n\r\n")), "400");
+
+    /*- The worker survived, and still handles requests -*/
+    assert_eq!(status(&chunked("5\r\nhello\r\n0\r\n\r\n")), "200");
+}
+
+#[test]
+fn refuses_chunks_without_crlf() {
+    assert_eq!(status(&chunked("5\r\nhelloXX0\r\n\r\n")), "400");
+}
+
+#[test]
+fn collects_trailers() {
+    for port in [PORT, STREAMING_PORT] {
+        let response: String = exchange(port, &[
+            b"POST /trailer HTTP/1.1\r\nConnection: close\r\nTransfer-Encoding: chunked\r\n\r\n",
+            b"5\r\nhello\r\n0\r\nX-Checksum: 1234\r\n\r\n",
+        ]);
+        assert_eq!(status(&response), "200");
+        assert!(response.ends_with("\r\n\r\n1234"));
+    }
+}
+
+#[test]
+fn refuses_too_many_trailers() {
+    /*- The last line goes over the limit of 16 KiB, so the
+    server has read everything once it responds -*/
+    let trailers: String = "X-Padding: 0123456789abcdef\r\n".repeat(565);
+    for (port, expected) in [(PORT, "431"), (STREAMING_PORT, "400")] {
+        let response: String = exchange(
+            port,
+            &[
+                b"POST /echo HTTP/1.1\r\nConnection: close\r\nTransfer-Encoding: chunked\r\n\r\n",
+                b"5\r\nhello\r\n0\r\n",
+                trailers.as_bytes(),
+            ],
+        );
+        assert_eq!(status(&response), expected);
+    }
+}
+
+fn responses(response: &str) -> usize {
+    response.matches("HTTP/1.1 ").count()
+}
+
+#[test]
+fn frames_pipelined_requests() {
+    let response: String = exchange(
+        PORT,
+        &[concat!(
+            "POST /echo HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello",
+            "POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nbig\r\n0\r\n\r\n",
+            "POST /echo HTTP/1.1\r\nConnection: close\r\nContent-Length: 5, 5\r\n\r\nworld",
+        )
+        .as_bytes()],
+    );
+    assert_eq!(responses(&response), 3);
+    let bodies: Vec<&str> = response
+        .split("HTTP/1.1 ")
+        .skip(1)
+        .map(|response| response.split("\r\n\r\n").nth(1).unwrap_or(""))
+        .collect();
+    assert_eq!(bodies, ["hello", "big", "world"]);
+}
+
+#[test]
+fn refuses_invalid_content_lengths() {
+    for content_length in ["abc", "+5", "5, 6", "5\r\nContent-Length: 6", ""] {
+        let request: String = format!(
+            "POST /echo HTTP/1.1\r\nContent-Length: {content_length}\r\n\r\nhello\
+            POST /echo HTTP/1.1\r\nContent-Length: 5\r\n\r\nworld"
+        );
+        let response: String = exchange(PORT, &[request.as_bytes()]);
+        assert_eq!(status(&response), "400");
+        assert_eq!(responses(&response), 1);
+    }
+}
+
+#[test]
+fn closes_connections_with_transfer_encoding_and_content_length() {
+    let response: String = exchange(
+        PORT,
+        &[concat!(
+            "POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n",
+            "5\r\nhello\r\n0\r\n\r\n",
+            "POST /echo HTTP/1.1\r\nContent-Length: 5\r\n\r\nworld",
+        )
+        .as_bytes()],
+    );
+    assert_eq!(responses(&response), 1);
+    assert!(response.contains("Connection: close\r\n"));
+    assert!(response.ends_with("\r\n\r\nhello"));
+}
@@ -0,0 +1,249 @@
+use responder::prelude::*;
+use responder::{match_route, MatchResult, RouteMatch, RouteRequest};
+
+const ROUTES: &[Route] = &[
+    Route::Get("", |_| {}),
+    Route::Stack(
+        "users",
+        &[
+            Route::Get(":id:u64:", |_| {}),
+            Route::Get(":name:", |_| {}),
+            Route::Post(":id:u64:", |_| {}),
+            Route::Stack("posts", &[Route::Get(":page?:u64:", |_| {})]),
+        ],
+    ),
+    Route::Method(Method::DELETE, "items/:id:", |_| {}),
+    Route::Method(Method::OPTIONS, "custom", |_| {}),
+    Route::Any("any", |_| {}),
+    Route::File("index", "./static/index.html"),
+    Route::Host("api.example.com", &[Route::Get("status", |_| {})]),
+    Route::Host("*.example.com", &[Route::Get("status", |_| {})]),
+    Route::Accept("application/json", &[Route::Get("data", |_| {})]),
+    Route::ControlledStack(|_| false, "controlled", &[Route::Get("", |_| {})]),
+    Route::Stack(
+        "groups/:group:",
+        &[Route::Get("members", |_| {}), Route::Fallback(|_| {})],
+    ),
+];
+
+/*- Match a request, and return the route which handles it -*/
+fn found(request: RouteRequest) -> RouteMatch<'static> {
+    match match_route(ROUTES, &request) {
+        MatchResult::Found(found) => found,
+        _ => panic!(
+            "no route matched {} {}",
+            request.method.as_str(),
+            request.path
+        ),
+    }
+}
+
+#[test]
+fn matches_root() {
+    assert_eq!(found(RouteRequest::new(Method::GET, "/")).template, "");
+}
+
+#[test]
+fn matches_params_in_order() {
+    let by_id = found(RouteRequest::new(Method::GET, "/users/12"));
+    assert_eq!(by_id.template, "users/:id:u64:");
+    assert_eq!(by_id.params["id"], "12");
+
+    /*- Constraints which don't match fall through to the next route -*/
+    let by_name = found(RouteRequest::new(Method::GET, "/users/john"));
+    assert_eq!(by_name.template, "users/:name:");
+    assert_eq!(by_name.params["name"], "john");
+}
+
+#[test]
+fn matches_nested_stacks() {
+    let page = found(RouteRequest::new(Method::GET, "/users/posts/3"));
+    assert_eq!(page.template, "users/posts/:page?:u64:");
+    assert_eq!(page.params["page"], "3");
+
+    /*- Optional last parameters can be left out, but routes are
+    tried in order, so an earlier parameter route matches first -*/
+    let first = found(RouteRequest::new(Method::GET, "/users/posts"));
+    assert_eq!(first.template, "users/:name:");
+    assert_eq!(first.params["name"], "posts");
+}
+
+#[test]
+fn dispatches_on_method() {
+    let post = found(RouteRequest::new(Method::POST, "/users/12"));
+    assert!(matches!(post.route, Route::Post(..)));
+
+    let head = found(RouteRequest::new(Method::HEAD, "/users/12"));
+    assert!(matches!(head.route, Route::Get(..)));
+
+    let delete = found(RouteRequest::new(Method::DELETE, "/items/a"));
+    assert!(matches!(delete.route, Route::Method(Method::DELETE, ..)));
+
+    let any = found(RouteRequest::new(Method::PATCH, "/any"));
+    assert!(matches!(any.route, Route::Any(..)));
+}
+
+#[test]
+fn method_not_allowed_lists_methods() {
+    match match_route(ROUTES, &RouteRequest::new(Method::PUT, "/users/12")) {
+        MatchResult::MethodNotAllowed(allowed) => {
+            assert_eq!(allowed, ["GET", "HEAD", "POST", "OPTIONS"])
+        }
+        _ => panic!("expected 405"),
+    };
+}
+
+#[test]
+fn preflights_are_answered_for_endpoints() {
+    assert!(found(RouteRequest::new(Method::OPTIONS, "/users/12")).preflight);
+    assert!(found(RouteRequest::new(Method::OPTIONS, "/index")).preflight);
+
+    /*- Unless the endpoint handles OPTIONS itself -*/
+    assert!(!found(RouteRequest::new(Method::OPTIONS, "/custom")).preflight);
+}
+
+#[test]
+fn files_are_only_served_for_get_and_head() {
+    let file = found(RouteRequest::new(Method::GET, "/index/"));
+    assert!(matches!(file.route, Route::File(_, "./static/index.html")));
+    let head = found(RouteRequest::new(Method::HEAD, "/index"));
+    assert!(matches!(head.route, Route::File(..)));
+    assert!(matches!(
+        match_route(ROUTES, &RouteRequest::new(Method::POST, "/index")),
+        MatchResult::MethodNotAllowed(_)
+    ));
+}
+
+#[test]
+fn host_stacks_match_their_host() {
+    let request = RouteRequest::new(Method::GET, "/status");
+    assert!(matches!(
+        match_route(ROUTES, &request),
+        MatchResult::NotFound
+    ));
+
+    let api = RouteRequest {
+        host: Some("API.example.com"),
+        ..request
+    };
+    assert_eq!(found(api).template, "status");
+}
+
+#[test]
+fn wildcard_hosts_capture_the_subdomain() {
+    let request = RouteRequest::new(Method::GET, "/status");
+    let tenant = found(RouteRequest {
+        host: Some("acme.example.com"),
+        ..request
+    });
+    assert_eq!(tenant.params["subdomain"], "acme");
+
+    /*- Exact hosts listed first take precedence -*/
+    let api = found(RouteRequest {
+        host: Some("api.example.com"),
+        ..request
+    });
+    assert!(!api.params.contains_key("subdomain"));
+
+    for host in ["example.com", "a.b.example.com", ".example.com"] {
+        let request = RouteRequest {
+            host: Some(host),
+            ..request
+        };
+        assert!(matches!(
+            match_route(ROUTES, &request),
+            MatchResult::NotFound
+        ));
+    }
+}
+
+#[test]
+fn accept_stacks_match_their_media_type() {
+    let request = RouteRequest::new(Method::GET, "/data");
+    assert_eq!(found(request).template, "data");
+
+    let json = RouteRequest {
+        accept: Some("application/*"),
+        ..request
+    };
+    assert_eq!(found(json).template, "data");
+
+    let html = RouteRequest {
+        accept: Some("text/html"),
+        ..request
+    };
+    assert!(matches!(
+        match_route(ROUTES, &html),
+        MatchResult::NotAcceptable
+    ));
+}
+
+#[test]
+fn origin_control_is_assumed_to_pass() {
+    assert_eq!(
+        found(RouteRequest::new(Method::GET, "/controlled")).template,
+        "controlled/"
+    );
+}
+
+#[test]
+fn unknown_paths_are_not_found() {
+    for path in ["/missing", "/users/12/extra", "/items"] {
+        assert!(matches!(
+            match_route(ROUTES, &RouteRequest::new(Method::GET, path)),
+            MatchResult::NotFound
+        ));
+    }
+}
+
+#[test]
+fn fallbacks_handle_unmatched_paths_in_their_stack() {
+    let fallback = found(RouteRequest::new(
+        Method::GET,
+        "/groups/admins/missing/deep",
+    ));
+    assert!(matches!(fallback.route, Route::Fallback(_)));
+    assert_eq!(fallback.template, "groups/:group:/*");
+    assert_eq!(fallback.params["group"], "admins");
+
+    /*- Routed paths with the wrong method still get a 405,
+    and paths outside of the stack aren't handled -*/
+    assert!(matches!(
+        match_route(
+            ROUTES,
+            &RouteRequest::new(Method::POST, "/groups/admins/members")
+        ),
+        MatchResult::MethodNotAllowed(_)
+    ));
+    assert!(matches!(
+        match_route(ROUTES, &RouteRequest::new(Method::GET, "/groups")),
+        MatchResult::NotFound
+    ));
+
+    /*- Fallbacks outside of stacks are ignored -*/
+    assert!(matches!(
+        match_route(
+            &[Route::Get("", |_| {}), Route::Fallback(|_| {})],
+            &RouteRequest::new(Method::GET, "/missing")
+        ),
+        MatchResult::NotFound
+    ));
+}
+
+#[test]
+fn lists_routes_with_full_paths() {
+    let mut server = Server::new();
+    server.routes(ROUTES);
+    let routes: Vec<(Method, String)> = server.route_list();
+
+    assert_eq!(routes[0], (Method::GET, String::from("")));
+    for route in [
+        (Method::POST, "users/:id:u64:"),
+        (Method::GET, "users/posts/:page?:u64:"),
+        (Method::DELETE, "items/:id:"),
+        (Method::GET, "index"),
+        (Method::GET, "groups/:group:/members"),
+    ] {
+        assert!(routes.contains(&(route.0, route.1.to_string())));
+    }
+}